@@ -0,0 +1,397 @@
+/*
+    air cooling for small packs: fans blowing through the gaps between cell rows,
+    or plain natural convection up those same gaps. air moves along y, across the
+    cell columns laid out along x (see layout.rs); prisms and pouches form parallel
+    plate channels, cylinders a staggered tube bank.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// most flow the search for a required fan flow will consider, m3/s
+const AIR_FLOW_MAX: f32 = 10.0;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AirCoolingParams {
+    // air gap between neighbouring cell columns, m. cylinders are laid out in a holder
+    // with this gap, replacing any holder gap from the cell file.
+    pub gap: f32,
+    // K
+    pub ambient_temp: f32,
+    // hottest the cells may get at the design load, K
+    pub cell_temp_max: f32,
+    // pack current the cooling is sized for, A
+    pub design_current: f32,
+    // fan (or fans in parallel) as (flow m3/s, static pressure Pa) points with rising flow.
+    // leave out for natural convection only.
+    #[serde(default)]
+    pub fan_curve: Vec<(f32, f32)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AirCooling {
+    // heat to remove at the design current, W
+    pub heat: f32,
+    // still air: chimney flow up the gaps, and the cell surface temperature it holds
+    pub natural_h: f32,
+    pub natural_cell_temp: f32,
+    // forced: least flow (m3/s) and the pressure (Pa) it takes to hold cell_temp_max,
+    // None if no sensible flow does
+    pub required_flow: Option<(f32, f32)>,
+    // where the fan curve meets the pack's pressure drop: flow, pressure, and the
+    // outlet-air cell estimate there (see AirGeometry::outlet_cell_temp)
+    pub fan_point: Option<(f32, f32, f32)>,
+}
+
+// what the air sees
+struct AirGeometry {
+    cylinders: bool,
+    // cell diameter (cylinders) or thickness (plates), m
+    d: f32,
+    gap: f32,
+    height: f32,
+    // along the flow
+    flow_length: f32,
+    // cells one after another along the flow
+    rows: usize,
+    // narrowest cross-section the air passes through, m2
+    free_area: f32,
+    // cell surface washed by air, m2
+    heated_area: f32,
+    // sides and top of the cell block, open to still air, m2
+    outer_area: f32,
+}
+
+impl AirGeometry {
+    fn new(layout: &Layout, gap: f32) -> Option<AirGeometry> {
+        let first = layout.cells.first()?;
+        let mut xs: Vec<f32> = layout.cells.iter().map(|c| c.pos[0]).collect();
+        xs.sort_by(|a, b| a.total_cmp(b));
+        xs.dedup_by(|a, b| (*a - *b).abs() < 0.25 * first.size[0]);
+        let columns = xs.len();
+        let rows = layout.cells.len().div_ceil(columns);
+        let (lo, hi) = layout.get_bounds();
+        let cylinders = matches!(first.module.shape, Shape::Cylinder);
+        let (d, height) = (first.size[0], first.size[2]);
+        // plates sit at the layout's stacking pitch; across the flow they take the gap
+        let width = if cylinders {
+            hi[0] - lo[0]
+        } else {
+            columns as f32 * (d + gap) + gap
+        };
+        let heated_area = layout
+            .cells
+            .iter()
+            .map(|c| match c.module.shape {
+                Shape::Cylinder => PI * c.size[0] * c.size[2],
+                _ => 2.0 * c.size[1] * c.size[2],
+            })
+            .sum();
+        Some(AirGeometry {
+            cylinders,
+            d,
+            gap,
+            height,
+            flow_length: hi[1] - lo[1],
+            rows,
+            // plates: one channel either side of every column
+            free_area: if cylinders { columns } else { columns + 1 } as f32 * gap * height,
+            heated_area,
+            outer_area: 2.0 * (width + (hi[1] - lo[1])) * height + width * (hi[1] - lo[1]),
+        })
+    }
+
+    // (h W/m2-K, pressure drop Pa) at a volume flow
+    fn forced(&self, air: &Coolant, temp: f32, flow: f32) -> (f32, f32) {
+        let s = air.get_state(temp);
+        let pr = air.get_prandtl(temp);
+        let v = flow / self.free_area.max(1e-9);
+        if self.cylinders {
+            // Zukauskas, single row-averaged cylinder in cross flow at the gap velocity;
+            // Jakob's friction factor for staggered banks
+            let re = s.density * v * self.d / s.viscosity;
+            let (c, m) = if re < 1000.0 {
+                (0.51, 0.5)
+            } else {
+                (0.26, 0.6)
+            };
+            let nu = c * re.max(1.0).powf(m) * pr.powf(0.37);
+            let st = (self.d + self.gap) / self.d;
+            let f = (0.25 + 0.1175 / (st - 1.0).max(0.05).powf(1.08)) * re.max(1.0).powf(-0.16);
+            let dp = 4.0 * f * self.rows as f32 * 0.5 * s.density * v * v;
+            (nu * s.conductivity / self.d, dp)
+        } else {
+            // parallel plates, Dh = 2 gap, with a Hausen-type entry length boost
+            let dh = 2.0 * self.gap;
+            let re = s.density * v * dh / s.viscosity;
+            let (nu_fd, f) = duct_flow(re, pr, 0.0);
+            let gz = dh / self.flow_length.max(1e-6) * re * pr;
+            let nu = if re < 2300.0 {
+                nu_fd + 0.03 * gz / (1.0 + 0.016 * gz.powf(2.0 / 3.0))
+            } else {
+                nu_fd
+            };
+            let dp = (f * self.flow_length / dh + 1.5) * 0.5 * s.density * v * v;
+            (nu * s.conductivity / dh, dp)
+        }
+    }
+
+    // outlet-air cell estimate (K) with `flow` blowing through: air warmed by all the
+    // heat upstream, plus the film drop at the surface-averaged h. the real outlet row's
+    // h differs from the average, so this is an estimate, not the hottest cell.
+    fn outlet_cell_temp(&self, air: &Coolant, t_in: f32, heat: f32, flow: f32) -> f32 {
+        let s = air.get_state(t_in);
+        let rise = heat / (s.density * s.specific_heat * flow.max(1e-9));
+        let (h, _) = self.forced(air, t_in + 0.5 * rise, flow);
+        t_in + rise + heat / (h * self.heated_area)
+    }
+
+    // still air rising up the gaps (vertical channels, height = cell height), Elenbaas:
+    // Nu = Ra'/24 (1 - exp(-35/Ra'))^(3/4), Ra' = Ra_gap * gap / height,
+    // plus the outside of the block with the simplified laminar h = 1.42 (dT/H)^(1/4).
+    // iterated since h depends on the temperature difference it sets.
+    // returns (h averaged over the cell surface in the gaps, cell surface K).
+    fn natural(&self, air: &Coolant, t_amb: f32, heat: f32) -> (f32, f32) {
+        let mut dt = 10.0_f32;
+        let mut h = 1.0;
+        for _ in 0..50 {
+            let t_film = t_amb + 0.5 * dt;
+            let s = air.get_state(t_film);
+            let nu_k = s.viscosity / s.density;
+            let alpha = s.conductivity / (s.density * s.specific_heat);
+            let ra = G * dt / t_film * self.gap.powi(3) / (nu_k * alpha) * self.gap / self.height;
+            let nu = ra / 24.0 * (1.0 - (-35.0 / ra.max(1e-6)).exp()).powf(0.75);
+            let h_out = 1.42 * (dt.max(0.01) / self.height).powf(0.25);
+            h = nu * s.conductivity / self.gap + h_out * self.outer_area / self.heated_area;
+            let next = heat / (h * self.heated_area);
+            if (next - dt).abs() < 1e-3 {
+                dt = next;
+                break;
+            }
+            dt = 0.5 * (dt + next);
+        }
+        (h, t_amb + dt)
+    }
+
+    // fan pressure falls and pack pressure drop rises with flow; bisect for the crossing.
+    // (flow, pressure, outlet-air cell estimate); the curve must already be checked.
+    fn fan_point(
+        &self,
+        air: &Coolant,
+        t_in: f32,
+        heat: f32,
+        curve: &[(f32, f32)],
+    ) -> Option<(f32, f32, f32)> {
+        let last = curve.last()?;
+        let (mut lo, mut hi) = (0.0_f32, last.0);
+        for _ in 0..60 {
+            let mid = 0.5 * (lo + hi);
+            if fan_pressure(curve, mid) > self.forced(air, t_in, mid).1 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Some((
+            lo,
+            fan_pressure(curve, lo),
+            self.outlet_cell_temp(air, t_in, heat, lo),
+        ))
+    }
+}
+
+// a fan curve needs finite, non-negative points in strictly rising flow, or
+// fan_pressure divides by zero between two points at one flow
+fn check_fan_curve(curve: &[(f32, f32)]) -> Result<(), Error> {
+    if let Some(p) = curve
+        .iter()
+        .find(|p| !(p.0.is_finite() && p.1.is_finite()) || p.0 < 0.0 || p.1 < 0.0)
+    {
+        return Err(Error::Invalid(format!(
+            "fan_curve: ({}, {}) isn't a flow and pressure",
+            p.0, p.1
+        )));
+    }
+    if let Some(w) = curve.windows(2).find(|w| w[1].0 <= w[0].0) {
+        return Err(Error::Invalid(format!(
+            "fan_curve: flow has to rise from point to point, got {} then {} m3/s",
+            w[0].0, w[1].0
+        )));
+    }
+    Ok(())
+}
+
+// static pressure of a fan curve at a flow; zero past its last point
+fn fan_pressure(curve: &[(f32, f32)], flow: f32) -> f32 {
+    match curve.iter().position(|p| p.0 >= flow) {
+        None => 0.0,
+        Some(0) => curve[0].1,
+        Some(i) => {
+            let (a, b) = (curve[i - 1], curve[i]);
+            a.1 + (flow - a.0) / (b.0 - a.0) * (b.1 - a.1)
+        }
+    }
+}
+
+impl Battery {
+    // the layout with cylinders held `gap` apart, so the tube bank the air sees is the
+    // one the gap describes. prism and pouch columns keep their stacking pitch; the
+    // gap goes into the channels and block width in AirGeometry.
+    fn get_air_layout(&self, gap: f32) -> Layout {
+        let module_array = self
+            .module_array
+            .iter()
+            .map(|ma| {
+                let mut ma = *ma;
+                if matches!(ma.module.shape, Shape::Cylinder) {
+                    let mut h = ma.module.holder.unwrap_or_default();
+                    h.gap = gap;
+                    ma.module.holder = Some(h);
+                }
+                ma
+            })
+            .collect();
+        Battery {
+            module_array,
+            e_params: None,
+            m_params: None,
+            t_params: None,
+        }
+        .get_layout()
+    }
+
+    pub fn analyze_air_cooling(&self, ap: &AirCoolingParams) -> Result<AirCooling, Error> {
+        check_fan_curve(&ap.fan_curve)?;
+        let gap = ap.gap.max(1e-4);
+        let geom = AirGeometry::new(&self.get_air_layout(gap), gap)
+            .ok_or_else(|| Error::Infeasible("nothing to cool: the pack has no cells".to_string()))?;
+        let air = air();
+        let heat = self.get_design_heat(ap.design_current, ap.cell_temp_max);
+        let (natural_h, natural_cell_temp) = geom.natural(&air, ap.ambient_temp, heat);
+
+        // cell temperature only falls with flow, so bisect for the least that works
+        let required_flow =
+            if geom.outlet_cell_temp(&air, ap.ambient_temp, heat, AIR_FLOW_MAX) > ap.cell_temp_max {
+                None
+            } else {
+                let (mut lo, mut hi) = (0.0_f32, AIR_FLOW_MAX);
+                for _ in 0..60 {
+                    let mid = 0.5 * (lo + hi);
+                    if geom.outlet_cell_temp(&air, ap.ambient_temp, heat, mid) > ap.cell_temp_max {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                Some((hi, geom.forced(&air, ap.ambient_temp, hi).1))
+            };
+
+        let fan_point = geom.fan_point(&air, ap.ambient_temp, heat, &ap.fan_curve);
+
+        Ok(AirCooling {
+            heat,
+            natural_h,
+            natural_cell_temp,
+            required_flow,
+            fan_point,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // air with temperature-independent properties, so the answers can be worked by hand
+    fn still_air() -> Coolant {
+        Coolant {
+            table: vec![FluidState {
+                temp: 300.0,
+                density: 1.2,
+                specific_heat: 1000.0,
+                viscosity: 1.8e-5,
+                conductivity: 0.025,
+            }],
+            ..air()
+        }
+    }
+
+    // 1 m2 of plates 2 mm apart and 0.1 m tall, 0.2 m along the flow, 0.01 m2 free area
+    fn plates() -> AirGeometry {
+        AirGeometry {
+            cylinders: false,
+            d: 0.01,
+            gap: 0.002,
+            height: 0.1,
+            flow_length: 0.2,
+            rows: 20,
+            free_area: 0.01,
+            heated_area: 1.0,
+            outer_area: 0.0,
+        }
+    }
+
+    // narrow gaps are fully developed (Ra' ~ 0.55 here), so Nu = Ra'/24 and
+    // h dT = Q/A becomes C dT^2 = Q (T_amb + dT/2), C = k g gap^3 A / (nu alpha H 24)
+    #[test]
+    fn natural_convection_known_answer() {
+        let (q, t_amb) = (100.0, 300.0);
+        let (nu, alpha) = (1.8e-5 / 1.2, 0.025 / 1200.0);
+        let c = 0.025 * G * 0.002_f32.powi(3) / (nu * alpha * 0.1 * 24.0);
+        let dt = (0.5 * q + (0.25 * q * q + 4.0 * c * q * t_amb).sqrt()) / (2.0 * c);
+        let (h, t) = plates().natural(&still_air(), t_amb, q);
+        assert!((t - t_amb - dt).abs() < 0.05, "{} vs {}", t - t_amb, dt);
+        assert!((h * dt - q).abs() < 0.2);
+    }
+
+    // a flat 50 Pa fan against laminar plates: dp = 48 mu L v / Dh^2 + 0.75 rho v^2
+    // (96/Re friction plus 1.5 velocity heads), so 0.9 v^2 + 10.8 v = 50, v = 3.568 m/s
+    #[test]
+    fn fan_meets_laminar_plates() {
+        let (flow, dp, t) = plates()
+            .fan_point(&still_air(), 300.0, 100.0, &[(0.0, 50.0), (1.0, 50.0)])
+            .unwrap();
+        let v = (-10.8 + (10.8_f32.powi(2) + 4.0 * 0.9 * 50.0).sqrt()) / 1.8;
+        assert!((flow - 0.01 * v).abs() < 1e-4, "{} vs {}", flow, 0.01 * v);
+        assert!((dp - 50.0).abs() < 1e-3);
+        // Re 951, Gz = Dh/L Re Pr = 13.7: Nu = 8.235 + 0.03 Gz / (1 + 0.016 Gz^(2/3))
+        let gz = 0.02 * (1.2 * v * 0.004 / 1.8e-5) * 0.72;
+        let h = (8.235 + 0.03 * gz / (1.0 + 0.016 * gz.powf(2.0 / 3.0))) * 0.025 / 0.004;
+        let expected = 300.0 + 100.0 / (1.2 * 1000.0 * flow) + 100.0 / h;
+        assert!((t - expected).abs() < 0.01, "{} vs {}", t, expected);
+    }
+
+    #[test]
+    fn fan_curve_must_rise() {
+        let cell = read_cell("tesla_21700", std::path::Path::new("")).unwrap();
+        let b = Battery::new_from(cell, 4, 2);
+        let params = |fan_curve: Vec<(f32, f32)>| AirCoolingParams {
+            gap: 0.002,
+            ambient_temp: 298.15,
+            cell_temp_max: 333.15,
+            design_current: 10.0,
+            fan_curve,
+        };
+        assert!(b.analyze_air_cooling(&params(vec![(0.0, 50.0), (0.01, 0.0)])).is_ok());
+        for bad in &[vec![(0.0, 50.0), (0.0, 0.0)], vec![(0.01, 50.0), (0.0, 0.0)]] {
+            let r = b.analyze_air_cooling(&params(bad.clone()));
+            assert!(matches!(r, Err(Error::Invalid(_))));
+        }
+        let r = b.analyze_air_cooling(&params(vec![(f32::NAN, 50.0)]));
+        assert!(matches!(r, Err(Error::Invalid(_))));
+    }
+}
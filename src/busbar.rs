@@ -0,0 +1,536 @@
+/*
+    busbar synthesis: node-based router/solver on the 2D plane of the cell tops.
+    1. assign cells to parallel groups by sweeping the layout (serpentine) and chunking,
+       keeping whichever grouping needs the least copper.
+    2. connect each pair of consecutive series groups with a Manhattan-routed ladder:
+       spanning trees along each group, rungs between the groups.
+    3. solve the ladder for its effective resistance and size it for the group current.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// ohm-m at 20 C
+pub const COPPER_RESISTIVITY: f32 = 1.72e-8;
+
+// summary of a routed layout
+#[derive(Copy, Clone, Debug)]
+pub struct Routing {
+    pub junctions: usize,
+    // summed strip length, m
+    pub length: f32,
+    pub copper_mass: f32,
+    // sum of the junction resistances along the series path, ohms
+    pub resistance: f32,
+    // pairs of busbars on the same face that overlap (i.e. would short)
+    pub crossings: usize,
+}
+
+fn dist2(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]) * (a[0] - b[0]) + (a[1] - b[1]) * (a[1] - b[1])
+}
+
+// visit points line by line along `axis`, alternating direction (boustrophedon)
+fn sweep(points: &[[f32; 2]], axis: usize, tol: f32) -> Vec<usize> {
+    let other = 1 - axis;
+    let mut idx: Vec<usize> = (0..points.len()).collect();
+    idx.sort_by(|&a, &b| points[a][other].total_cmp(&points[b][other]));
+    let mut lines: Vec<Vec<usize>> = Vec::new();
+    for i in idx {
+        match lines.last_mut() {
+            Some(l) if (points[l[0]][other] - points[i][other]).abs() < tol => l.push(i),
+            _ => lines.push(vec![i]),
+        }
+    }
+    let mut order: Vec<usize> = Vec::new();
+    for (k, mut l) in lines.into_iter().enumerate() {
+        l.sort_by(|&a, &b| points[a][axis].total_cmp(&points[b][axis]));
+        if k % 2 == 1 {
+            l.reverse();
+        }
+        order.extend(l);
+    }
+    order
+}
+
+// estimated strip length of a grouping: a spanning tree along each group plus
+// a rung from every terminal to the nearest terminal of each neighbouring group
+fn assignment_cost(points: &[[f32; 2]], groups: &[Vec<usize>]) -> f32 {
+    let manh = |a: [f32; 2], b: [f32; 2]| (a[0] - b[0]).abs() + (a[1] - b[1]).abs();
+    let mut cost: f32 = 0.0;
+    for g in groups {
+        let pts: Vec<[f32; 2]> = g.iter().map(|&i| points[i]).collect();
+        cost += spanning_tree(&pts, 0..pts.len())
+            .iter()
+            .map(|&(u, v)| manh(pts[u], pts[v]))
+            .sum::<f32>();
+    }
+    for w in groups.windows(2) {
+        for (x, y) in [(&w[0], &w[1]), (&w[1], &w[0])].iter() {
+            for &i in x.iter() {
+                cost += y
+                    .iter()
+                    .map(|&j| manh(points[i], points[j]))
+                    .fold(f32::MAX, f32::min);
+            }
+        }
+    }
+    cost
+}
+
+// rectangles for an L-shaped (horizontal then vertical) strip of width w from a to b
+fn manhattan(a: [f32; 2], b: [f32; 2], w: f32) -> Vec<([f32; 2], [f32; 2])> {
+    let h = 0.5 * w;
+    let mut pieces: Vec<([f32; 2], [f32; 2])> = Vec::new();
+    if (a[0] - b[0]).abs() > 1e-6 {
+        pieces.push((
+            [a[0].min(b[0]) - h, a[1] - h],
+            [a[0].max(b[0]) + h, a[1] + h],
+        ));
+    }
+    if (a[1] - b[1]).abs() > 1e-6 {
+        pieces.push((
+            [b[0] - h, a[1].min(b[1]) - h],
+            [b[0] + h, a[1].max(b[1]) + h],
+        ));
+    }
+    pieces
+}
+
+// candidate strip routes from a to b, all the same Manhattan length: the two L bends
+// and the two Z shapes that jog halfway across
+fn routes(a: [f32; 2], b: [f32; 2], w: f32) -> Vec<Vec<([f32; 2], [f32; 2])>> {
+    let mid_x = [0.5 * (a[0] + b[0]), a[1]];
+    let mid_y = [a[0], 0.5 * (a[1] + b[1])];
+    let mut z_x = manhattan(a, mid_x, w);
+    z_x.extend(manhattan(b, mid_x, w));
+    let mut z_y = manhattan(a, mid_y, w);
+    z_y.extend(manhattan(mid_y, b, w));
+    vec![manhattan(a, b, w), manhattan(b, a, w), z_y, z_x]
+}
+
+// Prim's algorithm over a contiguous range of nodes, Manhattan distance
+fn spanning_tree(nodes: &[[f32; 2]], range: std::ops::Range<usize>) -> Vec<(usize, usize)> {
+    let idx: Vec<usize> = range.collect();
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    if idx.is_empty() {
+        return edges;
+    }
+    let d = |i: usize, j: usize| (nodes[i][0] - nodes[j][0]).abs() + (nodes[i][1] - nodes[j][1]).abs();
+    let mut in_tree = vec![false; idx.len()];
+    let mut best = vec![(f32::MAX, 0_usize); idx.len()];
+    in_tree[0] = true;
+    for k in 1..idx.len() {
+        best[k] = (d(idx[0], idx[k]), 0);
+    }
+    for _ in 1..idx.len() {
+        let u = (0..idx.len())
+            .filter(|&k| !in_tree[k])
+            .min_by(|&x, &y| best[x].0.total_cmp(&best[y].0))
+            .unwrap();
+        in_tree[u] = true;
+        let p = best[u].1;
+        edges.push((idx[p].min(idx[u]), idx[p].max(idx[u])));
+        for k in 0..idx.len() {
+            let dk = d(idx[u], idx[k]);
+            if !in_tree[k] && dk < best[k].0 {
+                best[k] = (dk, u);
+            }
+        }
+    }
+    edges
+}
+
+// node voltages of a resistor network for the given injected currents (summing to zero),
+// node 0 grounded. dense Gaussian elimination; networks here are a few hundred nodes at most.
+fn solve_network(n: usize, edges: &[(usize, usize)], g: &[f32], inject: &[f32]) -> Vec<f32> {
+    if n < 2 {
+        return vec![0.0; n];
+    }
+    let m = n - 1;
+    let mut a = vec![vec![0.0_f64; m + 1]; m];
+    for (&(u, v), &ge) in edges.iter().zip(g.iter()) {
+        let ge = ge as f64;
+        for &(x, y) in [(u, v), (v, u)].iter() {
+            if x > 0 {
+                a[x - 1][x - 1] += ge;
+                if y > 0 {
+                    a[x - 1][y - 1] -= ge;
+                }
+            }
+        }
+    }
+    for k in 1..n {
+        a[k - 1][m] = inject[k] as f64;
+    }
+    for col in 0..m {
+        let piv = (col..m)
+            .max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))
+            .unwrap();
+        a.swap(col, piv);
+        if a[col][col].abs() < 1e-30 {
+            continue;
+        }
+        let pivot = a[col].clone();
+        for (r, row) in a.iter_mut().enumerate() {
+            if r != col {
+                let f = row[col] / pivot[col];
+                if f != 0.0 {
+                    for (x, p) in row.iter_mut().zip(pivot.iter()).skip(col) {
+                        *x -= f * p;
+                    }
+                }
+            }
+        }
+    }
+    let mut v = vec![0.0_f32; n];
+    for k in 0..m {
+        if a[k][k].abs() > 1e-30 {
+            v[k + 1] = (a[k][m] / a[k][k]) as f32;
+        }
+    }
+    v
+}
+
+fn overlaps(a: &([f32; 2], [f32; 2]), b: &([f32; 2], [f32; 2])) -> bool {
+    a.0[0] < b.1[0] && b.0[0] < a.1[0] && a.0[1] < b.1[1] && b.0[1] < a.1[1]
+}
+
+impl Layout {
+    // orient cells along the series path. axial cells must alternate so links land on
+    // the top and bottom faces in turn. end-terminated groups can each be turned either way, so
+    // pick orientations for the whole chain at once (Viterbi over two states per group)
+    // minimizing rung length, with a heavy penalty for a link that can only be routed
+    // across a terminal it must not touch (typically at the turn of a serpentine row).
+    pub fn orient_cells(&mut self) {
+        let n = self.get_group_count();
+        let groups: Vec<Vec<usize>> = (0..n)
+            .map(|g| (0..self.cells.len()).filter(|&k| self.cells[k].group == g).collect())
+            .collect();
+        let terminals = |cells: &[CellPlacement], g: &[usize], flip: bool| {
+            g.iter()
+                .map(|&k| {
+                    let mut c = cells[k];
+                    c.flip = flip;
+                    c.get_terminals()
+                })
+                .collect::<Vec<([f32; 2], [f32; 2])>>()
+        };
+        let link_cost = |cells: &[CellPlacement], a: &[usize], fa: bool, b: &[usize], fb: bool| {
+            let (ta, tb) = (terminals(cells, a, fa), terminals(cells, b, fb));
+            let pad = cells[a[0]].size[1].min(cells[b[0]].size[1]) * 0.2;
+            let keep_out: Vec<([f32; 2], [f32; 2])> = ta
+                .iter()
+                .map(|t| t.1)
+                .chain(tb.iter().map(|t| t.0))
+                .map(|t| ([t[0] - 0.5 * pad, t[1] - 0.5 * pad], [t[0] + 0.5 * pad, t[1] + 0.5 * pad]))
+                .collect();
+            let mut cost: f32 = 0.0;
+            for (p, _) in &ta {
+                let q = tb
+                    .iter()
+                    .map(|t| t.1)
+                    .min_by(|x, y| dist2(*p, *x).total_cmp(&dist2(*p, *y)))
+                    .unwrap();
+                cost += (p[0] - q[0]).abs() + (p[1] - q[1]).abs();
+                let blocked = |r: Vec<([f32; 2], [f32; 2])>| {
+                    r.iter().any(|x| keep_out.iter().any(|f| overlaps(x, f)))
+                };
+                if routes(*p, q, 0.5 * pad).into_iter().all(blocked) {
+                    cost += 1e3;
+                }
+            }
+            cost
+        };
+
+        let mut flips: Vec<bool> = (0..n).map(|g| g % 2 == 1).collect();
+        let end_terminated = groups
+            .iter()
+            .all(|g| g.iter().all(|&k| !self.cells[k].module.is_axial()));
+        if end_terminated && n > 1 && groups.iter().all(|g| !g.is_empty()) {
+            // cost[g][state], back[g][state]
+            let mut cost = vec![[0.0_f32; 2]; n];
+            let mut back = vec![[0_usize; 2]; n];
+            for g in 1..n {
+                for sb in 0..2 {
+                    let mut best = (f32::MAX, 0);
+                    for (sa, prev) in cost[g - 1].iter().enumerate() {
+                        let c = prev
+                            + link_cost(&self.cells, &groups[g - 1], sa == 1, &groups[g], sb == 1);
+                        if c < best.0 {
+                            best = (c, sa);
+                        }
+                    }
+                    cost[g][sb] = best.0;
+                    back[g][sb] = best.1;
+                }
+            }
+            let mut state = if cost[n - 1][0] <= cost[n - 1][1] { 0 } else { 1 };
+            for g in (0..n).rev() {
+                flips[g] = state == 1;
+                state = back[g][state];
+            }
+        }
+        for (g, members) in groups.iter().enumerate() {
+            for &k in members {
+                self.cells[k].flip = flips[g];
+            }
+        }
+    }
+
+    // re-assign cells of each ModuleArray to series groups of `parallel` cells.
+    // candidates are the placement's own grouping and serpentine sweeps along x and y
+    // chunked into groups; the one needing the least copper wins.
+    pub fn assign_groups(&mut self, b: &Battery) {
+        let mut first_group: usize = 0;
+        for (i, ma) in b.module_array.iter().enumerate() {
+            let idx: Vec<usize> = (0..self.cells.len())
+                .filter(|&k| self.cells[k].array == i)
+                .collect();
+            let p = ma.parallel.max(1) as usize;
+            if idx.is_empty() {
+                continue;
+            }
+            let points: Vec<[f32; 2]> = idx
+                .iter()
+                .map(|&k| [self.cells[k].pos[0], self.cells[k].pos[1]])
+                .collect();
+            let pitch = ma.module.get_pitch();
+            let tol = 0.25 * pitch[0].min(pitch[1]);
+
+            let mut placed: Vec<Vec<usize>> = Vec::new();
+            for (local, &k) in idx.iter().enumerate() {
+                let g = self.cells[k].group - first_group;
+                if placed.len() <= g {
+                    placed.resize(g + 1, Vec::new());
+                }
+                placed[g].push(local);
+            }
+            let mut candidates = vec![placed];
+            for axis in 0..2 {
+                let order = sweep(&points, axis, tol);
+                candidates.push(order.chunks(p).map(|c| c.to_vec()).collect());
+            }
+            let mut best: Option<(f32, Vec<Vec<usize>>)> = None;
+            for groups in candidates {
+                let cost = assignment_cost(&points, &groups);
+                if best.as_ref().is_none_or(|(c, _)| cost < *c) {
+                    best = Some((cost, groups));
+                }
+            }
+            let groups = best.map(|b| b.1).unwrap_or_default();
+            for (g, members) in groups.iter().enumerate() {
+                for (m, &local) in members.iter().enumerate() {
+                    let c = &mut self.cells[idx[local]];
+                    c.group = first_group + g;
+                    c.member = m;
+                }
+            }
+            first_group += groups.len();
+        }
+        self.orient_cells();
+    }
+
+    // connect every pair of consecutive series groups with a ladder: a minimum spanning
+    // tree collects each group's terminals (the parallel connection) and every terminal
+    // gets a rung to the nearest terminal of the other group (the series connection).
+    // each edge is routed as an L or Z of axis-aligned strips, picking the shape that stays
+    // off other cells' terminals, with a pad under every terminal. the ladder is solved as a
+    // resistor network with the group current shared equally between cells. the sheet is
+    // sized for the junction current (cell current times the parallel count) shared by the
+    // rungs that carry it across.
+    pub fn route_busbars(&mut self) -> Routing {
+        self.busbars.clear();
+        for g in 1..self.get_group_count() {
+            let (a, b) = (self.get_group(g - 1), self.get_group(g));
+            if a.is_empty() || b.is_empty() {
+                continue;
+            }
+            let m = a[0].module;
+            let size = a[0].size;
+            let top = !m.is_axial() || !a[0].flip;
+            // keep strips and pads narrower than a cell so neighbours stay clear
+            let (strip_w, pad) = match m.shape {
+                Shape::Cylinder => (0.35 * size[0], 0.6 * size[0]),
+                _ => {
+                    let w = (0.2 * size[1]).min(0.6 * size[0]);
+                    (w, w)
+                }
+            };
+            let current = m.max_current_continuous * a.len().min(b.len()) as f32;
+
+            // nodes: + terminals of g - 1 inject current, - terminals of g take it out
+            let na = a.len();
+            let mut nodes: Vec<[f32; 2]> = Vec::new();
+            let mut inject: Vec<f32> = Vec::new();
+            for c in &a {
+                nodes.push(c.get_terminals().0);
+                inject.push(1.0 / na as f32);
+            }
+            for c in &b {
+                nodes.push(c.get_terminals().1);
+                inject.push(-1.0 / b.len() as f32);
+            }
+            // every other terminal on this face must stay clear of the copper
+            let foreign: Vec<([f32; 2], [f32; 2])> = self
+                .cells
+                .iter()
+                .filter(|c| c.group + 1 != g && c.group != g)
+                .flat_map(|c| {
+                    let (p, n) = c.get_terminals();
+                    vec![p, n]
+                })
+                .chain(a.iter().map(|c| c.get_terminals().1))
+                .chain(b.iter().map(|c| c.get_terminals().0))
+                .filter(|t| !nodes.iter().any(|n| dist2(*n, *t) < 1e-12))
+                .map(|t| {
+                    (
+                        [t[0] - 0.5 * pad, t[1] - 0.5 * pad],
+                        [t[0] + 0.5 * pad, t[1] + 0.5 * pad],
+                    )
+                })
+                .collect();
+
+            let mut edges: Vec<(usize, usize)> = Vec::new();
+            edges.extend(spanning_tree(&nodes, 0..na));
+            edges.extend(spanning_tree(&nodes, na..nodes.len()));
+            for i in 0..nodes.len() {
+                let others = if i < na { na..nodes.len() } else { 0..na };
+                let j = others
+                    .min_by(|&x, &y| {
+                        dist2(nodes[i], nodes[x]).total_cmp(&dist2(nodes[i], nodes[y]))
+                    })
+                    .unwrap();
+                let e = (i.min(j), i.max(j));
+                if !edges.contains(&e) {
+                    edges.push(e);
+                }
+            }
+
+            let mut pieces: Vec<([f32; 2], [f32; 2])> = Vec::new();
+            let mut length: f32 = 0.0;
+            let mut lengths: Vec<f32> = Vec::new();
+            for &(u, v) in &edges {
+                let (p, q) = (nodes[u], nodes[v]);
+                let candidates = routes(p, q, strip_w);
+                let route = candidates
+                    .iter()
+                    .find(|r| !r.iter().any(|x| foreign.iter().any(|f| overlaps(x, f))))
+                    .unwrap_or(&candidates[0]);
+                pieces.extend(route.iter().cloned());
+                let l = ((p[0] - q[0]).abs() + (p[1] - q[1]).abs()).max(1e-4);
+                lengths.push(l);
+                length += l;
+            }
+            for p in &nodes {
+                pieces.push((
+                    [p[0] - 0.5 * pad, p[1] - 0.5 * pad],
+                    [p[0] + 0.5 * pad, p[1] + 0.5 * pad],
+                ));
+            }
+
+            // solve for unit sheet thickness; currents don't depend on it, resistance
+            // scales with 1 / thickness.
+            let conductance: Vec<f32> = lengths
+                .iter()
+                .map(|l| strip_w / (COPPER_RESISTIVITY * l))
+                .collect();
+            let v = solve_network(nodes.len(), &edges, &conductance, &inject);
+            let rungs = edges.iter().filter(|&&(x, y)| (x < na) != (y < na)).count().max(1);
+            let thickness = (current / (BUSBAR_CURRENT_DENSITY * strip_w * rungs as f32))
+                .max(BUSBAR_MIN_THICKNESS);
+            let r_unit: f32 = v.iter().zip(inject.iter()).map(|(x, y)| x * y).sum();
+
+            self.busbars.push(Busbar {
+                from_group: g - 1,
+                to_group: g,
+                pieces,
+                z: if top { size[2] } else { -thickness },
+                thickness,
+                top,
+                length,
+                resistance: r_unit / thickness,
+            });
+        }
+        self.get_routing()
+    }
+
+    pub fn get_routing(&self) -> Routing {
+        let mut crossings: usize = 0;
+        for (i, a) in self.busbars.iter().enumerate() {
+            for b in self.busbars.iter().skip(i + 1) {
+                if a.top == b.top && a.pieces.iter().any(|p| b.pieces.iter().any(|q| overlaps(p, q))) {
+                    crossings += 1;
+                }
+            }
+        }
+        Routing {
+            junctions: self.busbars.len(),
+            length: self.busbars.iter().map(|b| b.length).sum(),
+            copper_mass: self.busbars.iter().map(|b| b.get_mass()).sum(),
+            resistance: self.busbars.iter().map(|b| b.resistance).sum(),
+            crossings,
+        }
+    }
+}
+
+impl Battery {
+    // cell resistance plus the routed busbars
+    pub fn get_ir_dc_total(&self, soc: &f32) -> f32 {
+        self.get_ir_dc(soc) + self.get_layout().get_routing().resistance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // routed copper for 96S1P and 96S2P of 202 Ah cells is 0.5-3% of the cell mass
+    #[test]
+    fn copper_mass_is_plausible() {
+        let cell = read_cell("lfp_202ah", std::path::Path::new("")).unwrap();
+        for &parallel in &[1, 2] {
+            let b = Battery::new_from(cell, 96, parallel);
+            let routing = b.get_layout().get_routing();
+            let cells = cell.mass * (96 * parallel) as f32;
+            let share = routing.copper_mass / cells;
+            assert!(
+                share > 0.005 && share < 0.03,
+                "96S{}P: {:.1} kg of copper for {:.0} kg of cells",
+                parallel,
+                routing.copper_mass,
+                cells
+            );
+        }
+    }
+
+    // overlapping pieces count once
+    #[test]
+    fn busbar_area_is_the_union_of_pieces() {
+        let b = Busbar {
+            from_group: 0,
+            to_group: 1,
+            pieces: vec![([0.0, 0.0], [0.02, 0.01]), ([0.01, 0.0], [0.03, 0.01])],
+            z: 0.0,
+            thickness: 1e-3,
+            top: true,
+            length: 0.03,
+            resistance: 0.0,
+        };
+        assert!((b.get_area() - 3e-4).abs() < 1e-9);
+    }
+}
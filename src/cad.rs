@@ -0,0 +1,329 @@
+/*
+    CAD abstraction layer ("step 3.5"): turns a Layout into a list of simple solids,
+    then writes those out for CAD software. each cell, busbar and the enclosure is a
+    separate named body so it can be selected and replaced after import.
+    implemented: OpenSCAD script, ASCII STL. unimplemented: STEP.
+    all output is in millimeters.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+use std::fmt::Write;
+
+// segments used to approximate a cylinder in STL and OpenSCAD
+const CYL_SEGMENTS: usize = 24;
+const MM: f32 = 1000.0;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Solid {
+    // upright cylinder: center of the bounding box, diameter, height
+    Cylinder { center: [f32; 3], d: f32, h: f32 },
+    // axis-aligned box: center and size
+    Cuboid { center: [f32; 3], size: [f32; 3] },
+    // hollow axis-aligned box: outer corners and wall thickness
+    Shell { lo: [f32; 3], hi: [f32; 3], wall: f32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BodyKind {
+    Cell,
+    Busbar,
+    Enclosure,
+    Component,
+}
+
+#[derive(Clone, Debug)]
+pub struct Body {
+    pub name: String,
+    pub kind: BodyKind,
+    pub solids: Vec<Solid>,
+}
+
+// simple closed box around the layout
+#[derive(Copy, Clone, Debug)]
+pub struct Enclosure {
+    // wall thickness, m
+    pub wall: f32,
+    // gap between the cell/busbar block and the inside of the wall, m
+    pub clearance: f32,
+}
+
+impl Default for Enclosure {
+    fn default() -> Enclosure {
+        Enclosure {
+            wall: 3e-3,
+            clearance: 10e-3,
+        }
+    }
+}
+
+impl Layout {
+    pub fn get_bodies(&self, enclosure: Option<Enclosure>) -> Vec<Body> {
+        let mut bodies: Vec<Body> = Vec::new();
+        for (i, c) in self.cells.iter().enumerate() {
+            let solid = match c.module.shape {
+                Shape::Cylinder => Solid::Cylinder {
+                    center: c.pos,
+                    d: c.size[0],
+                    h: c.size[2],
+                },
+                _ => Solid::Cuboid {
+                    center: c.pos,
+                    size: c.size,
+                },
+            };
+            bodies.push(Body {
+                name: format!("cell_{}_g{}_m{}", i, c.group, c.member),
+                kind: BodyKind::Cell,
+                solids: vec![solid],
+            });
+        }
+        for b in &self.busbars {
+            let solids = b
+                .pieces
+                .iter()
+                .map(|(lo, hi)| Solid::Cuboid {
+                    center: [
+                        0.5 * (lo[0] + hi[0]),
+                        0.5 * (lo[1] + hi[1]),
+                        b.z + 0.5 * b.thickness,
+                    ],
+                    size: [hi[0] - lo[0], hi[1] - lo[1], b.thickness],
+                })
+                .collect();
+            bodies.push(Body {
+                name: format!("busbar_{}_{}", b.from_group, b.to_group),
+                kind: BodyKind::Busbar,
+                solids,
+            });
+        }
+        for c in &self.components {
+            bodies.push(Body {
+                name: c.name.replace(' ', "_"),
+                kind: BodyKind::Component,
+                solids: vec![Solid::Cuboid {
+                    center: c.pos,
+                    size: c.size,
+                }],
+            });
+        }
+        if let Some(e) = enclosure {
+            let (mut lo, mut hi) = self.get_bounds();
+            for a in 0..3 {
+                lo[a] -= e.clearance + e.wall;
+                hi[a] += e.clearance + e.wall;
+            }
+            bodies.push(Body {
+                name: "enclosure".to_string(),
+                kind: BodyKind::Enclosure,
+                solids: vec![Solid::Shell {
+                    lo,
+                    hi,
+                    wall: e.wall,
+                }],
+            });
+        }
+        bodies
+    }
+}
+
+fn mm(v: [f32; 3]) -> [f32; 3] {
+    [v[0] * MM, v[1] * MM, v[2] * MM]
+}
+
+// one OpenSCAD module per body kind, so each can be shown/hidden or exported alone.
+pub fn to_openscad(bodies: &[Body]) -> String {
+    let mut out = String::new();
+    writeln!(out, "// generated by battgen; units are mm").unwrap();
+    writeln!(out, "$fn = {};", CYL_SEGMENTS).unwrap();
+    let kinds = [
+        (BodyKind::Cell, "cells", "SteelBlue"),
+        (BodyKind::Busbar, "busbars", "Chocolate"),
+        (BodyKind::Component, "components", "Gray"),
+        (BodyKind::Enclosure, "enclosure", "LightGray"),
+    ];
+    for (kind, module, _) in kinds.iter() {
+        writeln!(out, "\nmodule {}() {{", module).unwrap();
+        for b in bodies.iter().filter(|b| b.kind == *kind) {
+            writeln!(out, "    // {}", b.name).unwrap();
+            for s in &b.solids {
+                writeln!(out, "    {}", openscad_solid(s)).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+    }
+    writeln!(out).unwrap();
+    for (kind, module, color) in kinds.iter() {
+        if bodies.iter().any(|b| b.kind == *kind) {
+            let alpha = if *kind == BodyKind::Enclosure { 0.3 } else { 1.0 };
+            writeln!(out, "color(\"{}\", {}) {}();", color, alpha, module).unwrap();
+        }
+    }
+    out
+}
+
+fn openscad_solid(s: &Solid) -> String {
+    match *s {
+        Solid::Cylinder { center, d, h } => {
+            let c = mm(center);
+            format!(
+                "translate([{:.3}, {:.3}, {:.3}]) cylinder(d = {:.3}, h = {:.3});",
+                c[0],
+                c[1],
+                c[2] - 0.5 * h * MM,
+                d * MM,
+                h * MM
+            )
+        }
+        Solid::Cuboid { center, size } => {
+            let (c, z) = (mm(center), mm(size));
+            format!(
+                "translate([{:.3}, {:.3}, {:.3}]) cube([{:.3}, {:.3}, {:.3}], center = true);",
+                c[0], c[1], c[2], z[0], z[1], z[2]
+            )
+        }
+        Solid::Shell { lo, hi, wall } => {
+            let (l, h) = (mm(lo), mm(hi));
+            let w = wall * MM;
+            format!(
+                "difference() {{ translate([{:.3}, {:.3}, {:.3}]) cube([{:.3}, {:.3}, {:.3}]); \
+                 translate([{:.3}, {:.3}, {:.3}]) cube([{:.3}, {:.3}, {:.3}]); }}",
+                l[0],
+                l[1],
+                l[2],
+                h[0] - l[0],
+                h[1] - l[1],
+                h[2] - l[2],
+                l[0] + w,
+                l[1] + w,
+                l[2] + w,
+                h[0] - l[0] - 2.0 * w,
+                h[1] - l[1] - 2.0 * w,
+                h[2] - l[2] - 2.0 * w
+            )
+        }
+    }
+}
+
+// ASCII STL with one `solid` block per body
+pub fn to_stl(bodies: &[Body]) -> String {
+    let mut out = String::new();
+    for b in bodies {
+        let mut tris: Vec<[[f32; 3]; 3]> = Vec::new();
+        for s in &b.solids {
+            triangulate(s, &mut tris);
+        }
+        writeln!(out, "solid {}", b.name).unwrap();
+        for t in &tris {
+            let n = normal(t);
+            writeln!(out, "  facet normal {:e} {:e} {:e}", n[0], n[1], n[2]).unwrap();
+            writeln!(out, "    outer loop").unwrap();
+            for v in t.iter() {
+                writeln!(out, "      vertex {:.4} {:.4} {:.4}", v[0], v[1], v[2]).unwrap();
+            }
+            writeln!(out, "    endloop").unwrap();
+            writeln!(out, "  endfacet").unwrap();
+        }
+        writeln!(out, "endsolid {}", b.name).unwrap();
+    }
+    out
+}
+
+fn normal(t: &[[f32; 3]; 3]) -> [f32; 3] {
+    let u = [t[1][0] - t[0][0], t[1][1] - t[0][1], t[1][2] - t[0][2]];
+    let v = [t[2][0] - t[0][0], t[2][1] - t[0][1], t[2][2] - t[0][2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let l = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if l > 0.0 {
+        [n[0] / l, n[1] / l, n[2] / l]
+    } else {
+        [0.0; 3]
+    }
+}
+
+// outward-facing triangles of a box given in mm; inward if `flip`
+fn box_tris(lo: [f32; 3], hi: [f32; 3], flip: bool, tris: &mut Vec<[[f32; 3]; 3]>) {
+    let p = |i: usize| -> [f32; 3] {
+        [
+            if i & 1 == 0 { lo[0] } else { hi[0] },
+            if i & 2 == 0 { lo[1] } else { hi[1] },
+            if i & 4 == 0 { lo[2] } else { hi[2] },
+        ]
+    };
+    // quads listed counter-clockwise seen from outside
+    let quads = [
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+    ];
+    for q in quads.iter() {
+        let (a, b, c, d) = (p(q[0]), p(q[1]), p(q[2]), p(q[3]));
+        if flip {
+            tris.push([a, c, b]);
+            tris.push([a, d, c]);
+        } else {
+            tris.push([a, b, c]);
+            tris.push([a, c, d]);
+        }
+    }
+}
+
+fn triangulate(s: &Solid, tris: &mut Vec<[[f32; 3]; 3]>) {
+    match *s {
+        Solid::Cylinder { center, d, h } => {
+            let c = mm(center);
+            let (r, z0, z1) = (0.5 * d * MM, c[2] - 0.5 * h * MM, c[2] + 0.5 * h * MM);
+            for i in 0..CYL_SEGMENTS {
+                let a0 = 2.0 * PI * i as f32 / CYL_SEGMENTS as f32;
+                let a1 = 2.0 * PI * (i + 1) as f32 / CYL_SEGMENTS as f32;
+                let (x0, y0) = (c[0] + r * a0.cos(), c[1] + r * a0.sin());
+                let (x1, y1) = (c[0] + r * a1.cos(), c[1] + r * a1.sin());
+                tris.push([[c[0], c[1], z1], [x0, y0, z1], [x1, y1, z1]]);
+                tris.push([[c[0], c[1], z0], [x1, y1, z0], [x0, y0, z0]]);
+                tris.push([[x0, y0, z0], [x1, y1, z0], [x1, y1, z1]]);
+                tris.push([[x0, y0, z0], [x1, y1, z1], [x0, y0, z1]]);
+            }
+        }
+        Solid::Cuboid { center, size } => {
+            let (c, z) = (mm(center), mm(size));
+            box_tris(
+                [c[0] - 0.5 * z[0], c[1] - 0.5 * z[1], c[2] - 0.5 * z[2]],
+                [c[0] + 0.5 * z[0], c[1] + 0.5 * z[1], c[2] + 0.5 * z[2]],
+                false,
+                tris,
+            );
+        }
+        Solid::Shell { lo, hi, wall } => {
+            let (l, h, w) = (mm(lo), mm(hi), wall * MM);
+            box_tris(l, h, false, tris);
+            box_tris(
+                [l[0] + w, l[1] + w, l[2] + w],
+                [h[0] - w, h[1] - w, h[2] - w],
+                true,
+                tris,
+            );
+        }
+    }
+}
@@ -0,0 +1,190 @@
+/*
+    liquid cold plate sizing. the plate sits under the whole cell block (z = 0) with
+    straight parallel rectangular channels running along x. heat goes from the cells
+    through the contact (heat_k) into the plate, then into the coolant by forced
+    convection; the coolant warms up from inlet to outlet on the way.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// channel sizes tried, m
+const CHANNEL_WIDTHS: [f32; 5] = [4e-3, 6e-3, 8e-3, 10e-3, 15e-3];
+const CHANNEL_HEIGHTS: [f32; 5] = [1.5e-3, 2e-3, 3e-3, 4e-3, 6e-3];
+// wall between neighbouring channels, m
+const CHANNEL_WEB: f32 = 2e-3;
+// inlet/outlet manifold and bend losses, in velocity heads
+const MANIFOLD_LOSS: f32 = 1.5;
+const PUMP_EFFICIENCY: f32 = 0.5;
+
+#[derive(Clone, Debug)]
+pub struct ColdPlateDesign {
+    // heat to remove at the design current, W
+    pub heat: f32,
+    // plate footprint needed to keep cells under cell_temp_max, and what's under the cells, m2
+    pub area_required: f32,
+    pub area_available: f32,
+    // plate length (along the channels) and width, m
+    pub plate_size: [f32; 2],
+    pub channel_width: f32,
+    pub channel_height: f32,
+    pub channels: usize,
+    // m3/s
+    pub flow_rate: f32,
+    // m/s
+    pub velocity: f32,
+    pub reynolds: f32,
+    // convection coefficient on the channel walls, W/m2-K
+    pub h: f32,
+    // K
+    pub outlet_temp: f32,
+    pub cell_temp: f32,
+    // Pa and W
+    pub pressure_drop: f32,
+    pub pump_power: f32,
+    // plate fits under the cells and the coolant stays liquid
+    pub feasible: bool,
+}
+
+// fully developed Nusselt number and Darcy friction factor in a rectangular duct of
+// aspect ratio a <= 1. laminar: Shah & London fits. turbulent: Gnielinski with the
+// Petukhov friction factor; the 2300-3000 transition is blended linearly.
+pub fn duct_flow(re: f32, pr: f32, a: f32) -> (f32, f32) {
+    let a = a.clamp(0.0, 1.0);
+    let nu_lam = 8.235
+        * (1.0 - 2.0421 * a + 3.0853 * a.powi(2) - 2.4765 * a.powi(3) + 1.0578 * a.powi(4)
+            - 0.1861 * a.powi(5));
+    let f_lam = 96.0 / re.max(1.0)
+        * (1.0 - 1.3553 * a + 1.9467 * a.powi(2) - 1.7012 * a.powi(3) + 0.9564 * a.powi(4)
+            - 0.2537 * a.powi(5));
+    let turbulent = |re: f32| {
+        let f = (0.790 * re.ln() - 1.64).powi(-2);
+        let nu = (f / 8.0) * (re - 1000.0) * pr
+            / (1.0 + 12.7 * (f / 8.0).sqrt() * (pr.powf(2.0 / 3.0) - 1.0));
+        (nu, f)
+    };
+    if re < 2300.0 {
+        (nu_lam, f_lam)
+    } else if re < 3000.0 {
+        let (nu_t, f_t) = turbulent(3000.0);
+        let x = (re - 2300.0) / 700.0;
+        (nu_lam + x * (nu_t - nu_lam), f_lam + x * (f_t - f_lam))
+    } else {
+        turbulent(re)
+    }
+}
+
+impl Battery {
+    // sizes the cold plate for t_params. every channel size in CHANNEL_WIDTHS x
+    // CHANNEL_HEIGHTS is tried; the design that fits with the least pump power wins,
+    // or the one closest to fitting if none do.
+    pub fn design_cold_plate(&self) -> Result<ColdPlateDesign, Error> {
+        let tp = self.t_params.as_ref().ok_or_else(|| {
+            Error::Infeasible("no thermal parameters to size a cold plate for".to_string())
+        })?;
+        let layout = self.get_layout();
+        let (lo, hi) = layout.get_bounds();
+        let (length, width) = (hi[0] - lo[0], hi[1] - lo[1]);
+        let area = length * width;
+        if area <= 0.0 {
+            return Err(Error::Infeasible(
+                "nothing to cool: the pack has no footprint".to_string(),
+            ));
+        }
+        // only the cell bottoms touch the plate
+        let contact: f32 = layout
+            .cells
+            .iter()
+            .map(|c| match c.module.shape {
+                Shape::Cylinder => 0.25 * PI * c.size[0] * c.size[0],
+                _ => c.size[0] * c.size[1],
+            })
+            .sum();
+        let phi = (contact / area).min(1.0);
+
+        let heat = self.get_design_heat(tp.design_current, tp.cell_temp_max);
+        let probe = tp.coolant.get_state(tp.inlet_temp);
+        let flow = if tp.flow_rate > 0.0 {
+            tp.flow_rate
+        } else {
+            heat / (probe.density * probe.specific_heat * tp.coolant_rise.max(0.1))
+        };
+        let rise = heat / (probe.density * probe.specific_heat * flow);
+        let outlet = tp.inlet_temp + rise;
+        let fluid = tp.coolant.get_state(tp.inlet_temp + 0.5 * rise);
+        let pr = tp.coolant.get_prandtl(fluid.temp);
+        // hottest cells sit over the outlet
+        let dt = tp.cell_temp_max - outlet;
+
+        let mut best: Option<ColdPlateDesign> = None;
+        for &w in CHANNEL_WIDTHS.iter() {
+            for &h in CHANNEL_HEIGHTS.iter() {
+                let n = ((width / (w + CHANNEL_WEB)).floor() as usize).max(1);
+                let dh = 2.0 * w * h / (w + h);
+                let v = flow / (n as f32 * w * h);
+                let re = fluid.density * v * dh / fluid.viscosity;
+                let (nu, f) = duct_flow(re, pr, h.min(w) / h.max(w));
+                let h_conv = nu * fluid.conductivity / dh;
+                // channel floor plus side walls (treated as fully effective fins),
+                // spread over the channel pitch
+                let h_plate = h_conv * (w + 2.0 * h) / (w + CHANNEL_WEB);
+                let u = 1.0 / (1.0 / (tp.heat_k * phi) + 1.0 / h_plate);
+                let area_required = if dt > 0.0 {
+                    heat / (u * dt)
+                } else {
+                    f32::INFINITY
+                };
+                let dp = (f * length / dh + MANIFOLD_LOSS) * 0.5 * fluid.density * v * v;
+                let pump = dp * flow / PUMP_EFFICIENCY;
+                let feasible = area_required <= area
+                    && tp.inlet_temp > tp.coolant.freezing_point
+                    && (tp.coolant.boiling_point <= 0.0 || outlet < tp.coolant.boiling_point);
+                let design = ColdPlateDesign {
+                    heat,
+                    area_required,
+                    area_available: area,
+                    plate_size: [length, width],
+                    channel_width: w,
+                    channel_height: h,
+                    channels: n,
+                    flow_rate: flow,
+                    velocity: v,
+                    reynolds: re,
+                    h: h_conv,
+                    outlet_temp: outlet,
+                    cell_temp: outlet + heat / (u * area),
+                    pressure_drop: dp,
+                    pump_power: pump,
+                    feasible,
+                };
+                let better = match &best {
+                    None => true,
+                    Some(b) => match (design.feasible, b.feasible) {
+                        (true, false) => true,
+                        (false, true) => false,
+                        (true, true) => design.pump_power < b.pump_power,
+                        (false, false) => design.area_required < b.area_required,
+                    },
+                };
+                if better {
+                    best = Some(design);
+                }
+            }
+        }
+        best.ok_or_else(|| Error::Infeasible("no cold plate channel sizes to try".to_string()))
+    }
+}
@@ -0,0 +1,460 @@
+/*
+    coolant property library and mixing rules.
+    tables are (temperature, density, specific heat, viscosity, conductivity) at 1 atm,
+    rounded from handbook data; good enough for sizing, not for certification.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// J/mol-K
+const GAS_CONSTANT: f32 = 8.314;
+
+// rows of [K, kg/m3, J/kg-K, Pa-s, W/m-K]
+fn table(rows: &[[f32; 5]]) -> Vec<FluidState> {
+    rows.iter()
+        .map(|r| FluidState {
+            temp: r[0],
+            density: r[1],
+            specific_heat: r[2],
+            viscosity: r[3],
+            conductivity: r[4],
+        })
+        .collect()
+}
+
+pub fn water() -> Coolant {
+    Coolant {
+        name: "water".to_string(),
+        table: table(&[
+            [273.15, 999.8, 4217.0, 1.792e-3, 0.561],
+            [293.15, 998.2, 4182.0, 1.002e-3, 0.598],
+            [313.15, 992.2, 4179.0, 0.653e-3, 0.631],
+            [333.15, 983.2, 4185.0, 0.467e-3, 0.654],
+            [353.15, 971.8, 4197.0, 0.355e-3, 0.670],
+            [373.15, 958.4, 4216.0, 0.282e-3, 0.679],
+        ]),
+        freezing_point: 273.15,
+        boiling_point: 373.15,
+        flammable: false,
+        // not pure water, but anything that's been through a pump and a radiator
+        conductive: true,
+        molar_mass: 0.018015,
+        heat_of_fusion: Some(6010.0),
+        heat_of_vaporization: Some(40650.0),
+    }
+}
+
+pub fn ethylene_glycol() -> Coolant {
+    Coolant {
+        name: "ethylene-glycol".to_string(),
+        table: table(&[
+            [273.15, 1130.8, 2294.0, 57.4e-3, 0.242],
+            [293.15, 1116.6, 2382.0, 19.9e-3, 0.249],
+            [313.15, 1101.4, 2474.0, 9.5e-3, 0.256],
+            [333.15, 1087.4, 2562.0, 5.2e-3, 0.260],
+            [353.15, 1072.6, 2650.0, 3.2e-3, 0.261],
+            [373.15, 1058.0, 2742.0, 2.1e-3, 0.263],
+        ]),
+        freezing_point: 260.15,
+        boiling_point: 470.45,
+        flammable: true,
+        conductive: true,
+        molar_mass: 0.06207,
+        heat_of_fusion: Some(9960.0),
+        heat_of_vaporization: None,
+    }
+}
+
+pub fn propylene_glycol() -> Coolant {
+    Coolant {
+        name: "propylene-glycol".to_string(),
+        table: table(&[
+            [273.15, 1051.0, 2420.0, 243e-3, 0.206],
+            [293.15, 1036.0, 2500.0, 58.1e-3, 0.202],
+            [313.15, 1022.0, 2580.0, 18.4e-3, 0.199],
+            [333.15, 1007.0, 2660.0, 8.0e-3, 0.196],
+            [353.15, 992.0, 2740.0, 4.2e-3, 0.193],
+            [373.15, 977.0, 2820.0, 2.5e-3, 0.190],
+        ]),
+        // glass transition; it doesn't really crystallize, so no heat of fusion
+        freezing_point: 214.15,
+        boiling_point: 461.15,
+        flammable: true,
+        conductive: true,
+        molar_mass: 0.07609,
+        heat_of_fusion: None,
+        heat_of_vaporization: None,
+    }
+}
+
+// hydrofluoroether immersion fluid (3M Novec 7500 class)
+pub fn novec_7500() -> Coolant {
+    Coolant {
+        name: "novec-7500".to_string(),
+        table: table(&[
+            [273.15, 1665.0, 1095.0, 1.90e-3, 0.068],
+            [298.15, 1614.0, 1128.0, 1.24e-3, 0.065],
+            [323.15, 1560.0, 1165.0, 0.85e-3, 0.062],
+            [353.15, 1497.0, 1205.0, 0.60e-3, 0.059],
+        ]),
+        freezing_point: 173.15,
+        boiling_point: 401.15,
+        flammable: false,
+        conductive: false,
+        molar_mass: 0.414,
+        heat_of_fusion: None,
+        heat_of_vaporization: None,
+    }
+}
+
+// light mineral / PAO oil used for immersion and transformer cooling
+pub fn mineral_oil() -> Coolant {
+    Coolant {
+        name: "mineral-oil".to_string(),
+        table: table(&[
+            [273.15, 890.0, 1800.0, 40e-3, 0.136],
+            [298.15, 875.0, 1880.0, 18e-3, 0.134],
+            [323.15, 860.0, 1960.0, 9e-3, 0.132],
+            [353.15, 840.0, 2060.0, 4.8e-3, 0.129],
+        ]),
+        // pour point
+        freezing_point: 228.15,
+        boiling_point: 573.15,
+        flammable: true,
+        conductive: false,
+        molar_mass: 0.3,
+        heat_of_fusion: None,
+        heat_of_vaporization: None,
+    }
+}
+
+// dry air at 1 atm
+pub fn air() -> Coolant {
+    Coolant {
+        name: "air".to_string(),
+        table: table(&[
+            [253.15, 1.395, 1006.0, 1.63e-5, 0.0228],
+            [273.15, 1.292, 1006.0, 1.72e-5, 0.0243],
+            [298.15, 1.184, 1007.0, 1.85e-5, 0.0262],
+            [323.15, 1.092, 1008.0, 1.96e-5, 0.0281],
+            [353.15, 0.999, 1009.0, 2.09e-5, 0.0300],
+        ]),
+        freezing_point: 0.0,
+        boiling_point: 0.0,
+        flammable: false,
+        conductive: false,
+        molar_mass: 0.02897,
+        heat_of_fusion: None,
+        heat_of_vaporization: None,
+    }
+}
+
+impl Coolant {
+    // properties at temp (K); linear between table rows, held constant past the ends
+    pub fn get_state(&self, temp: f32) -> FluidState {
+        let t = &self.table;
+        if t.is_empty() {
+            return FluidState {
+                temp,
+                density: 0.0,
+                specific_heat: 0.0,
+                viscosity: 0.0,
+                conductivity: 0.0,
+            };
+        }
+        let i = t.iter().position(|r| r.temp >= temp).unwrap_or(t.len() - 1);
+        if i == 0 || t[i].temp < temp {
+            return FluidState { temp, ..t[i] };
+        }
+        let (a, b) = (t[i - 1], t[i]);
+        let f = (temp - a.temp) / (b.temp - a.temp);
+        let lerp = |x: f32, y: f32| x + f * (y - x);
+        FluidState {
+            temp,
+            density: lerp(a.density, b.density),
+            specific_heat: lerp(a.specific_heat, b.specific_heat),
+            viscosity: lerp(a.viscosity, b.viscosity),
+            conductivity: lerp(a.conductivity, b.conductivity),
+        }
+    }
+
+    pub fn get_prandtl(&self, temp: f32) -> f32 {
+        let s = self.get_state(temp);
+        s.specific_heat * s.viscosity / s.conductivity
+    }
+
+    // everything built in: pure fluids plus the usual premixes
+    pub fn library() -> Vec<Coolant> {
+        vec![
+            water(),
+            ethylene_glycol(),
+            propylene_glycol(),
+            CoolantMix::glycol(ethylene_glycol(), 0.5).to_coolant("water-eg-50"),
+            CoolantMix::glycol(propylene_glycol(), 0.3).to_coolant("water-pg-30"),
+            CoolantMix::glycol(propylene_glycol(), 0.5).to_coolant("water-pg-50"),
+            novec_7500(),
+            mineral_oil(),
+            air(),
+        ]
+    }
+
+    // library lookup. "water-eg-NN" and "water-pg-NN" give any glycol mass percentage.
+    pub fn from_name(name: &str) -> Option<Coolant> {
+        let name = name.to_lowercase();
+        if let Some(c) = Coolant::library().into_iter().find(|c| c.name == name) {
+            return Some(c);
+        }
+        let glycol = match name.get(..9) {
+            Some("water-eg-") => ethylene_glycol(),
+            Some("water-pg-") => propylene_glycol(),
+            _ => return None,
+        };
+        let pct: f32 = name[9..].parse().ok()?;
+        if !(0.0..=100.0).contains(&pct) {
+            return None;
+        }
+        Some(CoolantMix::glycol(glycol, pct / 100.0).to_coolant(&name))
+    }
+}
+
+impl CoolantMix {
+    pub fn new(parts: Vec<(Coolant, f32)>) -> CoolantMix {
+        let total: f32 = parts.iter().map(|p| p.1).sum::<f32>().max(1e-9);
+        let (coolants, fractions) = parts.into_iter().map(|(c, f)| (c, f / total)).unzip();
+        CoolantMix {
+            coolants,
+            fractions,
+        }
+    }
+
+    // water with `fraction` glycol by mass
+    pub fn glycol(glycol: Coolant, fraction: f32) -> CoolantMix {
+        CoolantMix::new(vec![(water(), 1.0 - fraction), (glycol, fraction)])
+    }
+
+    fn get_mole_fractions(&self) -> Vec<f32> {
+        let n: Vec<f32> = self
+            .coolants
+            .iter()
+            .zip(&self.fractions)
+            .map(|(c, w)| w / c.molar_mass)
+            .collect();
+        let total: f32 = n.iter().sum::<f32>().max(1e-12);
+        n.iter().map(|x| x / total).collect()
+    }
+
+    // effective properties at temp (K):
+    //  density: ideal volume additivity, 1/rho = sum(w / rho)
+    //  specific heat: mass-weighted
+    //  viscosity: log-mass-weighted (Arrhenius-type); tracks glycol/water within ~20%
+    //  conductivity: Filippov, k = sum(w k) - 0.72 sum_pairs(w_i w_j |k_i - k_j|)
+    pub fn get_state(&self, temp: f32) -> FluidState {
+        let states: Vec<FluidState> = self.coolants.iter().map(|c| c.get_state(temp)).collect();
+        let w = &self.fractions;
+        let mut mix = FluidState {
+            temp,
+            density: 0.0,
+            specific_heat: 0.0,
+            viscosity: 0.0,
+            conductivity: 0.0,
+        };
+        let mut inv_rho = 0.0;
+        for (s, &wi) in states.iter().zip(w) {
+            inv_rho += wi / s.density;
+            mix.specific_heat += wi * s.specific_heat;
+            mix.viscosity += wi * s.viscosity.ln();
+            mix.conductivity += wi * s.conductivity;
+        }
+        mix.density = 1.0 / inv_rho;
+        mix.viscosity = mix.viscosity.exp();
+        for i in 0..states.len() {
+            for j in i + 1..states.len() {
+                mix.conductivity -=
+                    0.72 * w[i] * w[j] * (states[i].conductivity - states[j].conductivity).abs();
+            }
+        }
+        mix
+    }
+
+    // liquidus temperature, K. each component with a heat of fusion gets an ideal
+    // (Schröder-van Laar) freezing curve, 1/T = 1/Tm - R ln(x) / dHfus; the mixture starts
+    // to freeze at the highest of them. ideal solutions only: real 50/50 water/EG freezes
+    // around 236 K, this gives ~249 K, so it errs on the safe side.
+    pub fn get_freezing_point(&self) -> f32 {
+        self.get_first_to_freeze().map(|(t, _)| t).unwrap_or_else(|| {
+            self.coolants
+                .iter()
+                .zip(&self.fractions)
+                .map(|(c, w)| w * c.freezing_point)
+                .sum()
+        })
+    }
+
+    // (liquidus, heat of fusion) of the component that crystallizes first
+    fn get_first_to_freeze(&self) -> Option<(f32, f32)> {
+        let x = self.get_mole_fractions();
+        self.coolants
+            .iter()
+            .zip(&x)
+            .filter_map(|(c, &xi)| {
+                let h = c.heat_of_fusion?;
+                if xi <= 0.0 {
+                    return None;
+                }
+                Some((1.0 / (1.0 / c.freezing_point - GAS_CONSTANT * xi.ln() / h), h))
+            })
+            .fold(None, |m: Option<(f32, f32)>, t| match m {
+                Some(m) if m.0 >= t.0 => Some(m),
+                _ => Some(t),
+            })
+    }
+
+    // bubble point, K: the volatile components (those with a heat of vaporization) boil
+    // with the ideal elevation 1/T = 1/Tb + R ln(x) / dHvap; everything else is a solute.
+    pub fn get_boiling_point(&self) -> f32 {
+        let x = self.get_mole_fractions();
+        self.coolants
+            .iter()
+            .zip(&x)
+            .filter_map(|(c, &xi)| {
+                let h = c.heat_of_vaporization?;
+                if xi <= 0.0 {
+                    return None;
+                }
+                Some(1.0 / (1.0 / c.boiling_point + GAS_CONSTANT * xi.ln() / h))
+            })
+            .fold(None, |m: Option<f32>, t| Some(m.map_or(t, |m| m.min(t))))
+            .unwrap_or_else(|| {
+                self.coolants
+                    .iter()
+                    .map(|c| c.boiling_point)
+                    .fold(f32::MAX, f32::min)
+            })
+    }
+
+    // tabulates the mixture on the component tables' temperatures, so a mix can be
+    // used anywhere a Coolant is
+    pub fn to_coolant(&self, name: &str) -> Coolant {
+        let mut temps: Vec<f32> = self
+            .coolants
+            .iter()
+            .flat_map(|c| c.table.iter().map(|r| r.temp))
+            .collect();
+        temps.sort_by(|a, b| a.total_cmp(b));
+        temps.dedup_by(|a, b| (*a - *b).abs() < 1e-3);
+        // don't extrapolate past where every component has data
+        let lo = self
+            .coolants
+            .iter()
+            .filter_map(|c| c.table.first())
+            .map(|r| r.temp)
+            .fold(f32::MIN, f32::max);
+        let hi = self
+            .coolants
+            .iter()
+            .filter_map(|c| c.table.last())
+            .map(|r| r.temp)
+            .fold(f32::MAX, f32::min);
+        let flammable_mass: f32 = self
+            .coolants
+            .iter()
+            .zip(&self.fractions)
+            .filter(|(c, _)| c.flammable)
+            .map(|(_, w)| w)
+            .sum();
+        let x = self.get_mole_fractions();
+        Coolant {
+            name: name.to_string(),
+            table: temps
+                .into_iter()
+                .filter(|&t| t >= lo && t <= hi)
+                .map(|t| self.get_state(t))
+                .collect(),
+            freezing_point: self.get_freezing_point(),
+            boiling_point: self.get_boiling_point(),
+            // water-rich glycol mixes don't sustain a flame
+            flammable: flammable_mass > 0.5,
+            conductive: self.coolants.iter().any(|c| c.conductive),
+            molar_mass: x
+                .iter()
+                .zip(&self.coolants)
+                .map(|(xi, c)| xi * c.molar_mass)
+                .sum(),
+            // whatever freezes out first sets the latent heat, so a premix mixed again
+            // still gets a freezing curve
+            heat_of_fusion: self.get_first_to_freeze().map(|(_, h)| h),
+            heat_of_vaporization: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // mass fractions 0.5/0.5 of a 1000 and a 2000 kg/m3 fluid with made-up properties
+    fn pair() -> CoolantMix {
+        let fluid = |name: &str, rho: f32, cp: f32, mu: f32, k: f32| Coolant {
+            name: name.to_string(),
+            table: table(&[[300.0, rho, cp, mu, k]]),
+            freezing_point: 250.0,
+            boiling_point: 400.0,
+            flammable: false,
+            conductive: false,
+            molar_mass: 0.1,
+            heat_of_fusion: None,
+            heat_of_vaporization: None,
+        };
+        CoolantMix::new(vec![
+            (fluid("a", 1000.0, 4000.0, 1e-3, 0.6), 1.0),
+            (fluid("b", 2000.0, 2000.0, 1e-1, 0.2), 1.0),
+        ])
+    }
+
+    #[test]
+    fn mixing_rules() {
+        let s = pair().get_state(300.0);
+        // 1 / (0.5/1000 + 0.5/2000)
+        assert!((s.density - 1333.33).abs() < 0.1);
+        assert!((s.specific_heat - 3000.0).abs() < 0.1);
+        // exp(0.5 ln 1e-3 + 0.5 ln 1e-1) = 1e-2
+        assert!((s.viscosity - 1e-2).abs() < 1e-5);
+        // 0.4 - 0.72 * 0.25 * 0.4
+        assert!((s.conductivity - 0.328).abs() < 1e-4);
+    }
+
+    #[test]
+    fn freezing_point_depression() {
+        // no heat of fusion anywhere: mass-weighted
+        assert!((pair().get_freezing_point() - 250.0).abs() < 1e-3);
+        // 50/50 water/EG by mass is x_water = 0.775; 1/T = 1/273.15 - R ln(0.775) / 6010
+        let mix = CoolantMix::glycol(ethylene_glycol(), 0.5);
+        let t = mix.get_freezing_point();
+        assert!((t - 249.4).abs() < 0.5, "{}", t);
+        // pure water doesn't move
+        let pure = CoolantMix::glycol(ethylene_glycol(), 0.0);
+        assert!((pure.get_freezing_point() - 273.15).abs() < 1e-2);
+        // the tabulated premix keeps water's heat of fusion, so a dash of oil (x = 0.99)
+        // depresses it by ~0.9 K instead of averaging to 247.3 K by mass
+        let premix = mix.to_coolant("water-eg-50");
+        assert_eq!(premix.heat_of_fusion, Some(6010.0));
+        let oiled = CoolantMix::new(vec![(premix, 0.9), (mineral_oil(), 0.1)]);
+        let drop = t - oiled.get_freezing_point();
+        assert!(drop > 0.5 && drop < 1.5, "{}", drop);
+    }
+}
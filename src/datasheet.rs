@@ -0,0 +1,544 @@
+/*
+    importer for vendor spec sheets pasted as text, so cell files don't have to be
+    transcribed by hand. two layouts are understood: tables with one cell per line
+    ("3.2V 50Ah	1.41kg	186*136*31mm	3C"), and label/value sheets for a single cell
+    ("Nominal Voltage" on one line, "3.2V" on a later one). whatever a sheet doesn't say
+    keeps the chemistry default, or zero where there's none, and is listed in
+    ImportedCell::missing.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// between the two ends of a temperature range: -20~60°C, -10–45°C
+const RANGE_SEPARATORS: [char; 3] = ['~', '–', '-'];
+
+#[derive(Clone, Debug)]
+pub struct ImportedCell {
+    // model number if the sheet gives one, otherwise chemistry and capacity (lfp_50ah)
+    pub name: String,
+    // the table row it came from; empty for label/value sheets
+    pub source: String,
+    pub module: Module,
+    // cell file fields the sheet didn't give; they hold chemistry defaults (or zero)
+    pub missing: Vec<&'static str>,
+}
+
+// fields no chemistry has a default for; left off the sheet they're written as 0
+const ZEROED_FIELDS: [&str; 3] = ["dims", "mass", "rnom"];
+
+impl ImportedCell {
+    // missing fields that hold a chemistry default
+    pub fn get_defaulted(&self) -> Vec<&'static str> {
+        self.missing.iter().copied().filter(|f| !ZEROED_FIELDS.contains(f)).collect()
+    }
+
+    // missing fields set to 0, to be filled in by hand
+    pub fn get_zeroed(&self) -> Vec<&'static str> {
+        self.missing.iter().copied().filter(|f| ZEROED_FIELDS.contains(f)).collect()
+    }
+}
+
+// one number and the letters right after it, lowercased: (3.2, "v"), (50, "ah"),
+// (25, "°c"). start and end are char positions in the line.
+#[derive(Clone, Debug)]
+struct Quantity {
+    value: f32,
+    unit: String,
+    start: usize,
+    end: usize,
+}
+
+// what's been read off the sheet for one cell; temperatures in C
+#[derive(Clone, Debug, Default)]
+struct Sheet {
+    model: Option<String>,
+    vnom: Option<f32>,
+    vmin: Option<f32>,
+    vmax: Option<f32>,
+    q: Option<f32>,
+    mass: Option<f32>,
+    rnom: Option<f32>,
+    // continuous discharge, either in A or as a C-rate
+    current: Option<f32>,
+    c_rate: Option<f32>,
+    cycle_life: Option<f32>,
+    // m, in cell file order
+    dims: Option<Vec<f32>>,
+    discharge: Option<(f32, f32)>,
+    charge: Option<(f32, f32)>,
+    storage: Option<(f32, f32)>,
+}
+
+fn get_quantities(line: &str) -> Vec<Quantity> {
+    let c: Vec<char> = line.to_lowercase().replace('℃', "°c").chars().collect();
+    let digit_at = |i: usize| c.get(i).is_some_and(char::is_ascii_digit);
+    let mut out: Vec<Quantity> = Vec::new();
+    let mut i = 0;
+    while i < c.len() {
+        // a minus sign only when it isn't joining two things, as in 1-100pcs
+        let negative = c[i] == '-' && digit_at(i + 1) && (i == 0 || !c[i - 1].is_alphanumeric());
+        if !digit_at(i) && !negative {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        while digit_at(i) || (i < c.len() && c[i] == '.') {
+            i += 1;
+        }
+        let number: String = c[start..i].iter().collect();
+        let mut j = i;
+        while j < c.len() && c[j] == ' ' {
+            j += 1;
+        }
+        let unit_start = j;
+        while j < c.len() && (c[j].is_alphabetic() || c[j] == '°') {
+            j += 1;
+        }
+        let unit: String = c[unit_start..j].iter().collect();
+        let end = if unit.is_empty() { i } else { j };
+        if let Ok(value) = number.trim_end_matches('.').parse() {
+            out.push(Quantity {
+                value,
+                unit,
+                start,
+                end,
+            });
+        }
+        i = end;
+    }
+    // the low end of -10–45°C carries no unit of its own
+    for k in 1..out.len() {
+        let between: String = c[out[k - 1].end..out[k].start].iter().collect();
+        let mut between = between.trim().chars();
+        let joined = match (between.next(), between.next()) {
+            (Some(s), None) => RANGE_SEPARATORS.contains(&s),
+            _ => false,
+        };
+        if joined && out[k - 1].unit.is_empty() && out[k].unit == "°c" {
+            out[k - 1].unit = "°c".to_string();
+        }
+    }
+    out
+}
+
+// "186*136*31mm", "D26*W135*H170mm", "205*174*72*mm", "18*65mm" to cell file dims in m:
+// (height, width, thickness) for prisms, (diameter, length) for cylinders.
+// without labels the smallest of three is the thickness.
+fn parse_dims(word: &str) -> Option<Vec<f32>> {
+    let word = word.to_lowercase().replace('×', "*");
+    if !word.contains('*') {
+        return None;
+    }
+    let scale = if word.ends_with("cm") {
+        0.01
+    } else if word.ends_with("mm") || !word.ends_with('m') {
+        0.001
+    } else {
+        1.0
+    };
+    let mut parts: Vec<(Option<char>, f32)> = Vec::new();
+    for part in word.split('*') {
+        let label = part.chars().next().filter(|c| c.is_alphabetic());
+        let digits: String = part
+            .chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit() || *c == '.')
+            .collect();
+        if let Ok(v) = digits.parse::<f32>() {
+            parts.push((label, v * scale));
+        }
+    }
+    match parts.len() {
+        2 => {
+            let (a, b) = (parts[0].1, parts[1].1);
+            Some(vec![a.min(b), a.max(b)])
+        }
+        3 => {
+            let mut dims: [Option<f32>; 3] = [None; 3];
+            let mut rest: Vec<f32> = Vec::new();
+            for (label, v) in &parts {
+                let slot = match label {
+                    Some('h') => Some(0),
+                    Some('w') | Some('l') => Some(1),
+                    Some('d') | Some('t') => Some(2),
+                    _ => None,
+                };
+                match slot {
+                    Some(s) if dims[s].is_none() => dims[s] = Some(*v),
+                    _ => rest.push(*v),
+                }
+            }
+            if rest.len() == 3 {
+                let thinnest = (0..3)
+                    .min_by(|&a, &b| rest[a].total_cmp(&rest[b]))
+                    .unwrap_or(2);
+                let t = rest.remove(thinnest);
+                rest.push(t);
+            }
+            let mut rest = rest.into_iter();
+            Some(
+                dims.iter()
+                    .map(|d| d.or_else(|| rest.next()).unwrap_or(0.0))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+// nominal voltage is the best hint when a sheet never names its chemistry
+fn chem_from_voltage(v: f32) -> Option<Chem> {
+    if (3.1..=3.35).contains(&v) {
+        Some(Chem::LFP)
+    } else if (3.55..=3.8).contains(&v) {
+        Some(Chem::NMC)
+    } else if (2.2..=2.5).contains(&v) {
+        Some(Chem::LTO)
+    } else if (1.15..=1.3).contains(&v) {
+        Some(Chem::NiMH)
+    } else {
+        None
+    }
+}
+
+// a value the sheet gave goes into the module; a missing one is noted instead
+fn fill(missing: &mut Vec<&'static str>, field: &'static str, v: Option<f32>, slot: &mut f32) {
+    match v {
+        Some(x) => *slot = x,
+        None => missing.push(field),
+    }
+}
+
+impl Sheet {
+    // one line of the sheet. `label` is the label/value sheet heading the line sits
+    // under, and narrows what's taken from it: "Internal Resistance" lines also mention
+    // test rates and voltages. table rows have no label and give everything.
+    fn absorb(&mut self, line: &str, label: &str) {
+        let label = label.to_lowercase();
+        let text = format!("{} {}", label, line.to_lowercase());
+        let allows = |keys: &[&str]| label.is_empty() || keys.iter().any(|k| label.contains(k));
+        let discharge = text.contains("discharg");
+        let charge = !discharge && text.contains("charg");
+        let peak = text.contains("peak") || text.contains("pulse");
+
+        if label.contains("model") {
+            if self.model.is_none() {
+                self.model = Some(line.trim().to_string());
+            }
+            return;
+        }
+        if allows(&["size", "dimension"]) && self.dims.is_none() {
+            self.dims = line.split_whitespace().find_map(parse_dims);
+        }
+
+        let qs = get_quantities(line);
+        let temps: Vec<f32> = qs
+            .iter()
+            .filter(|q| q.unit.starts_with("°c"))
+            .map(|q| q.value)
+            .collect();
+        if temps.len() >= 2 && allows(&["temp"]) {
+            let range = (temps[0].min(temps[1]), temps[0].max(temps[1]));
+            let slot = if text.contains("storage") {
+                &mut self.storage
+            } else if charge {
+                &mut self.charge
+            } else {
+                &mut self.discharge
+            };
+            slot.get_or_insert(range);
+        }
+        for q in &qs {
+            let v = q.value;
+            match q.unit.as_str() {
+                "v" if allows(&["volt"]) => {
+                    let slot = if discharge {
+                        &mut self.vmin
+                    } else if charge {
+                        &mut self.vmax
+                    } else {
+                        &mut self.vnom
+                    };
+                    slot.get_or_insert(v);
+                }
+                "ah" if allows(&["capacity"]) => {
+                    self.q.get_or_insert(v);
+                }
+                "mah" if allows(&["capacity"]) => {
+                    self.q.get_or_insert(v / 1000.0);
+                }
+                "kg" if allows(&["weight", "mass"]) => {
+                    self.mass.get_or_insert(v);
+                }
+                "g" if allows(&["weight", "mass"]) => {
+                    self.mass.get_or_insert(v / 1000.0);
+                }
+                "mω" | "mohm" if allows(&["resistance", "impedance"]) => {
+                    self.rnom.get_or_insert(v / 1000.0);
+                }
+                "ω" | "ohm" if allows(&["resistance", "impedance"]) => {
+                    self.rnom.get_or_insert(v);
+                }
+                "c" if allows(&["current", "rate", "discharg"]) && !charge && !peak => {
+                    self.c_rate.get_or_insert(v);
+                }
+                "a" if allows(&["current", "rate", "discharg"]) && !charge && !peak => {
+                    self.current.get_or_insert(v);
+                }
+                "times" | "cycles" | "cycle" if allows(&["cycle", "life"]) => {
+                    self.cycle_life.get_or_insert(v);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // None without a capacity: that's not a cell. `context` is the whole sheet, for a
+    // chemistry named in a title or heading.
+    fn to_cell(&self, source: &str, context: &str) -> Option<ImportedCell> {
+        let q = self.q?;
+        let mut missing: Vec<&'static str> = Vec::new();
+        let chem = Chem::find_in(source)
+            .or_else(|| self.model.as_deref().and_then(Chem::find_in))
+            .or_else(|| Chem::find_in(context))
+            .or_else(|| self.vnom.and_then(chem_from_voltage));
+        if chem.is_none() {
+            missing.push("chem");
+        }
+        let chem = chem.unwrap_or(Chem::Other);
+
+        let dims = self.dims.clone().unwrap_or_default();
+        let (mass, rnom) = (self.mass.unwrap_or(0.0), self.rnom.unwrap_or(0.0));
+        let mut m = match dims.len() {
+            2 => Module::new_cyl_cell(dims[0], dims[1], mass, rnom, 0.0, q, chem),
+            3 => Module::new_rec_cell(dims[0], dims[1], dims[2], mass, rnom, q, chem),
+            _ => {
+                missing.push("dims");
+                Module::new_rec_cell(0.0, 0.0, 0.0, mass, rnom, q, chem)
+            }
+        };
+        if self.mass.is_none() {
+            missing.push("mass");
+        }
+        fill(&mut missing, "vnom", self.vnom, &mut m.vnom);
+        fill(&mut missing, "vmin", self.vmin, &mut m.vmin);
+        fill(&mut missing, "vmax", self.vmax, &mut m.vmax);
+        if self.rnom.is_none() {
+            missing.push("rnom");
+        }
+        let current = self.current.or_else(|| self.c_rate.map(|c| c * q));
+        fill(
+            &mut missing,
+            "max_current_continuous",
+            current,
+            &mut m.max_current_continuous,
+        );
+        fill(
+            &mut missing,
+            "cycle_life",
+            self.cycle_life,
+            &mut m.cycle_life,
+        );
+        // never on a datasheet, but the thermal models need it
+        missing.push("specific_heat");
+        match self.discharge {
+            Some((lo, hi)) => {
+                m.temp_min = Temperature::from_celsius(lo);
+                m.temp_max = Temperature::from_celsius(hi);
+            }
+            None => missing.extend(&["temp_max", "temp_min"]),
+        }
+        if let Some((lo, hi)) = self.charge {
+            m.temp_charge_min = Some(Temperature::from_celsius(lo));
+            m.temp_charge_max = Some(Temperature::from_celsius(hi));
+        }
+        if let Some((lo, hi)) = self.storage {
+            m.temp_storage_min = Some(Temperature::from_celsius(lo));
+            m.temp_storage_max = Some(Temperature::from_celsius(hi));
+        }
+
+        let name = match &self.model {
+            Some(model) => model
+                .to_lowercase()
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect(),
+            None => format!("{}_{}ah", chem.get_name(), q),
+        };
+        Some(ImportedCell {
+            name,
+            source: source.trim().replace('\t', "  "),
+            module: m,
+            missing,
+        })
+    }
+}
+
+// every cell on the sheet. a line with both a voltage and a capacity is a table row;
+// a sheet without any is read as label/value lines for one cell, where a line without
+// digits is the label for the lines under it.
+pub fn parse_datasheet(text: &str) -> Vec<ImportedCell> {
+    let is_row = |l: &str| {
+        let qs = get_quantities(l);
+        qs.iter().any(|q| q.unit == "v") && qs.iter().any(|q| q.unit == "ah" || q.unit == "mah")
+    };
+    let rows: Vec<&str> = text.lines().filter(|l| is_row(l)).collect();
+    let mut cells: Vec<ImportedCell> = Vec::new();
+    if rows.is_empty() {
+        let mut sheet = Sheet::default();
+        let mut label = "";
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if line.chars().any(|c| c.is_ascii_digit()) {
+                sheet.absorb(line, label);
+            } else {
+                label = line;
+            }
+        }
+        cells.extend(sheet.to_cell("", text));
+    } else {
+        for row in rows {
+            let mut sheet = Sheet::default();
+            sheet.absorb(row, "");
+            cells.extend(sheet.to_cell(row, text));
+        }
+    }
+
+    // names become file names, so they have to be unique: lfp_50ah, lfp_50ah_2, ...
+    let names: Vec<String> = cells.iter().map(|c| c.name.clone()).collect();
+    for (i, cell) in cells.iter_mut().enumerate() {
+        let taken = names[..i].iter().filter(|n| **n == names[i]).count();
+        if taken > 0 {
+            cell.name = format!("{}_{}", names[i], taken + 1);
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-4 * b.abs().max(1.0)
+    }
+
+    fn dims_are(cell: &ImportedCell, dims: &[f32]) -> bool {
+        let m = &cell.module;
+        let n = if matches!(m.shape, Shape::Cylinder) { 2 } else { 3 };
+        n == dims.len() && dims.iter().zip(m.dims.iter()).all(|(a, b)| close(*b, *a))
+    }
+
+    // one cell per row; kg, mm with the thickness last, and a C-rate for the current
+    #[test]
+    fn table_rows() {
+        let cells = parse_datasheet(include_str!("../examples/lfp_table"));
+        assert_eq!(cells.len(), 10);
+        let c = &cells[0];
+        assert_eq!(c.name, "lfp_50ah");
+        assert!(dims_are(c, &[0.186, 0.136, 0.031]));
+        assert!(close(c.module.mass, 1.41));
+        assert!(close(c.module.q, 50.0));
+        assert!(close(c.module.max_current_continuous, 150.0));
+        // a stray trailing star, and a mass with no decimals
+        let c = cells.iter().find(|c| c.name == "lfp_280ah").unwrap();
+        assert!(dims_are(c, &[0.205, 0.174, 0.072]));
+        assert!(close(c.module.max_current_continuous, 280.0));
+        assert!(close(cells.iter().find(|c| c.name == "lfp_200ah").unwrap().module.mass, 6.0));
+        assert_eq!(c.get_zeroed(), vec!["rnom"]);
+        assert_eq!(
+            c.get_defaulted(),
+            vec!["vmin", "vmax", "cycle_life", "specific_heat", "temp_max", "temp_min"]
+        );
+    }
+
+    // labelled D/W/H dims, upper case units, and a price column of 1-100PCS
+    #[test]
+    fn labelled_dims_and_price_columns() {
+        let cells = parse_datasheet(include_str!("../examples/lfp_table_qihua"));
+        assert_eq!(cells.len(), 12);
+        let c = &cells[0];
+        assert!(dims_are(c, &[0.170, 0.135, 0.026]));
+        assert!(close(c.module.mass, 1.33));
+        assert!(close(c.module.cycle_life, 6000.0));
+        // no label on the height: it takes the slot that's left
+        assert!(dims_are(&cells[4], &[0.126, 0.174, 0.048]));
+        let qs = get_quantities("Price  1-100PCS");
+        assert!(qs.iter().all(|q| q.value >= 0.0));
+        assert_eq!(qs.iter().map(|q| q.value).collect::<Vec<_>>(), vec![1.0, 100.0]);
+    }
+
+    // label/value sheet for one cell
+    #[test]
+    fn label_value_sheet() {
+        let cells = parse_datasheet(include_str!("../examples/202ah_lfp_example"));
+        assert_eq!(cells.len(), 1);
+        let c = &cells[0];
+        let m = &c.module;
+        assert_eq!(c.name, "rj-lfp54173200-176");
+        assert!(matches!(m.chem, Chem::LFP));
+        assert!(close(m.q, 202.0));
+        assert!(dims_are(c, &[0.2, 0.173, 0.054]));
+        assert!(close(m.mass, 4.1));
+        assert!(close(m.vmax, 3.65));
+        assert!(close(m.vmin, 2.5));
+        assert!(close(m.rnom, 0.0005));
+        // continuous 2C, not the charging rates above it
+        assert!(close(m.max_current_continuous, 404.0));
+        assert!(close(m.temp_min.celsius(), -20.0));
+        assert!(close(m.temp_max.celsius(), 70.0));
+        assert!(close(m.temp_charge_min.unwrap().celsius(), -10.0));
+        assert!(close(m.temp_charge_max.unwrap().celsius(), 55.0));
+        assert!(close(m.temp_storage_min.unwrap().celsius(), -10.0));
+        assert!(close(m.temp_storage_max.unwrap().celsius(), 45.0));
+        assert!(c.get_zeroed().is_empty());
+        assert_eq!(c.get_defaulted(), vec!["specific_heat"]);
+    }
+
+    // the low end of a range takes the unit of the high end
+    #[test]
+    fn range_units() {
+        let qs = get_quantities("≤1month: -10–45°C");
+        let temps: Vec<(f32, &str)> = qs.iter().map(|q| (q.value, q.unit.as_str())).collect();
+        assert_eq!(temps, vec![(1.0, "month"), (-10.0, "°c"), (45.0, "°c")]);
+    }
+
+    // mAh and grams, as on small cylindrical cells
+    #[test]
+    fn small_units() {
+        let cells = parse_datasheet("NMC 3.6V 2600mAh 46.5g 18*65mm\n");
+        let c = &cells[0];
+        assert!(close(c.module.q, 2.6));
+        assert!(close(c.module.mass, 0.0465));
+        assert!(dims_are(c, &[0.018, 0.065]));
+        assert_eq!(c.name, "nmc_2.6ah");
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let cells = parse_datasheet("3.2V 50Ah 1.41kg\n3.2V 50Ah 1.45kg\n3.2V 50Ah 1.5kg\n");
+        let names: Vec<&str> = cells.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["lfp_50ah", "lfp_50ah_2", "lfp_50ah_3"]);
+    }
+}
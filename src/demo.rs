@@ -18,31 +18,33 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use crate::*;
+use battgen::*;
+use colored::*;
+use crate::report::*;
 
-pub fn demo() {
+pub fn demo() -> Result<(), Error> {
     println!("Running demo...");
 
     let leaf_mod_file = "./examples/cells/leaf_2012.ron";
 
     println!("~~~~~~~Recreating 2012 Nissan LEAF pack:~~~~~~~~");
 
-    demo_from_filename(leaf_mod_file, 48, 1);
+    demo_from_filename(leaf_mod_file, 48, 1)?;
 
     println!("~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~");
     println!("making a pack from 200Ah cells....");
 
     let lfp_200_mod_file = "./examples/cells/lfp_200ah.ron";
     
-    demo_from_filename(lfp_200_mod_file, 96, 1);
+    demo_from_filename(lfp_200_mod_file, 96, 1)?;
 
     let lfp_202_mod_file = "./examples/cells/lfp_202ah.ron";
 
-    demo_from_filename(lfp_202_mod_file, 96, 1);
+    demo_from_filename(lfp_202_mod_file, 96, 1)?;
 
     let tesla_cell_file = "./examples/cells/tesla_21700.ron";
 
-    demo_from_filename(tesla_cell_file, 96, 10);
+    demo_from_filename(tesla_cell_file, 96, 10)?;
 
 /*
     println!("~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~");
@@ -55,32 +57,34 @@ pub fn demo() {
 
     demo_from_filename(lfp_75_mod_file, 96, 2);
 */
+    Ok(())
 }
 
 
 
 
 
-pub fn demo_from_filename(fname: &str, s: i32, p: i32) {
+pub fn demo_from_filename(fname: &str, s: i32, p: i32) -> Result<(), Error> {
     
     println!("{} {} {}S{}P", "Generating demo from module given".green(), fname, s, p);
         
-    let dmod = read_module(fname);
+    let dmod = read_cell(fname, std::path::Path::new(""))?;
 
-    dmod.print_mechanical();
-    dmod.print_mass();
-    dmod.print_electrical_nominal();
-    dmod.print_topology();
+    print_module_mechanical(&dmod);
+    print_module_mass(&dmod);
+    print_module_electrical(&dmod);
+    print_module_topology(&dmod);
 
 
     let newbat2 = Battery::new_from(dmod, s, p);
 
-    newbat2.print_topology();
-    newbat2.print_voltage();
-    newbat2.print_ah();
+    print_pack_topology(&newbat2);
+    print_pack_voltage(&newbat2);
+    print_pack_ah(&newbat2);
 
     println!("{} {} kWh", "Nominal pack capacity: ".blue(), newbat2.get_kwh_nominal());
 
     // this is broken. feel free to fix it.
     println!("{} {} m3", "Leaf pack volume: ".red(), newbat2.get_min_volume_packed());
+    Ok(())
 }
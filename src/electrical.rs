@@ -20,11 +20,11 @@
 
 use crate::*;
 
-const MAX_PARALLEL_SUB: i32 = 25;
-const MAX_SERIES_SUB: i32 = 25;
+use serde::de::{self, Deserializer};
+use serde::Serializer;
 
 // struct actually used throughout the program
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
 pub struct Module {
     // these are necessary for basic use
     pub shape: Shape,
@@ -45,14 +45,31 @@ pub struct Module {
     pub rnom: f32,
     // continuous maximum current in amps according to manufacturer
     pub max_current_continuous: f32,
-    // specific heat
-    // kJ/kg-K or J/g-k (equal)
+    // specific heat, J/kg-K (typically 800-1100 for cells)
     pub specific_heat: f32,
     // to 80% SoH at 1C charge/discharge
     pub cycle_life: f32,
-    // KELVIN
-    pub temp_max: f32,
-    pub temp_min: f32,
+    // discharge window; see temperature.rs for the rest of the operating window
+    pub temp_max: Temperature,
+    pub temp_min: Temperature,
+    // charging and storage windows; chemistry defaults otherwise
+    pub temp_charge_min: Option<Temperature>,
+    pub temp_charge_max: Option<Temperature>,
+    pub temp_storage_min: Option<Temperature>,
+    pub temp_storage_max: Option<Temperature>,
+    // thickness growth with SoC and aging; prismatic/pouch cells only, LFP defaults
+    // otherwise
+    pub swelling: Option<Swelling>,
+    // manufacturing tolerance on dims[2], meters; mostly matters for pouches
+    pub thickness_tolerance: f32,
+    // actual volume in m3 when it isn't implied by shape and dims (Custom)
+    pub volume: Option<f32>,
+    // spacing and holder plates; cylinders only
+    pub holder: Option<CellHolder>,
+    // runaway onset and energy, if the chemistry defaults don't fit
+    pub runaway: Option<Runaway>,
+    // anisotropic thermal conductivity, if the shape defaults don't fit
+    pub conductivity: Option<Conductivity>,
 }
 
 // A battery is made of arbitrary arrays of modules (only in series for now);
@@ -78,15 +95,19 @@ pub struct ModuleArray {
 
 // The cell's physical shape; sorry, hexagons aren't supported yet.
 // flow batteries aren't supported, obviously.
-#[derive(Copy, Clone, Debug, Deserialize)]
+// Custom is anything else described by its bounding box (dims) plus actual volume.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Shape {
     Prism,
     Cylinder,
+    Pouch,
+    Custom,
     Other,
 }
 
-// how the cell is terminated, for design (mech) tools
-#[derive(Copy, Clone, Debug, Deserialize)]
+// how the cell is terminated, for design (mech) tools.
+// End: both terminals/tabs on one end. Axial: one at each end (cylinders, some pouches).
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Term {
     End,
     Axial,
@@ -94,7 +115,7 @@ pub enum Term {
 }
 
 // Cell means a single chemical cell. these aren't strictly enforced (yet).
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ModType {
     Cell,
     Module,
@@ -102,7 +123,7 @@ pub enum ModType {
 
 // Actual chemistry of cell; these are arbitrarily chosen and not guaranteed
 // to represent anything in the real world.
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Chem {
     NMC,
     LFP,
@@ -113,62 +134,192 @@ pub enum Chem {
     Other,
 }
 
+// what cell files may call each variant, matched case-insensitively.
+// the first name for a variant is the one written back out.
+const SHAPE_NAMES: [(&str, Shape); 8] = [
+    ("prism", Shape::Prism),
+    ("prismatic", Shape::Prism),
+    ("cylinder", Shape::Cylinder),
+    ("cylindrical", Shape::Cylinder),
+    ("pouch", Shape::Pouch),
+    ("custom", Shape::Custom),
+    ("other", Shape::Other),
+    ("unknown", Shape::Other),
+];
+
+const TERM_NAMES: [(&str, Term); 4] = [
+    ("end", Term::End),
+    ("axial", Term::Axial),
+    ("other", Term::Other),
+    ("unknown", Term::Other),
+];
+
+const MODTYPE_NAMES: [(&str, ModType); 2] = [("cell", ModType::Cell), ("module", ModType::Module)];
+
+const CHEM_NAMES: [(&str, Chem); 13] = [
+    ("nmc", Chem::NMC),
+    ("ncm", Chem::NMC),
+    ("lfp", Chem::LFP),
+    ("lifepo4", Chem::LFP),
+    ("lmo", Chem::LMO),
+    ("limn2o4", Chem::LMO),
+    ("nca", Chem::NCA),
+    ("lto", Chem::LTO),
+    ("li4ti5o12", Chem::LTO),
+    ("nimh", Chem::NiMH),
+    ("ni-mh", Chem::NiMH),
+    ("other", Chem::Other),
+    ("unknown", Chem::Other),
+];
+
+// a cell file string to its variant; anything unlisted is an error naming what is accepted
+fn from_name<'de, D: Deserializer<'de>, T: Copy>(
+    d: D,
+    what: &str,
+    names: &[(&str, T)],
+) -> Result<T, D::Error> {
+    let s = String::deserialize(d)?;
+    let key = s.trim().to_lowercase();
+    match names.iter().find(|(n, _)| *n == key) {
+        Some(&(_, v)) => Ok(v),
+        None => Err(de::Error::custom(format!(
+            "unknown {} \"{}\", expected one of: {}",
+            what,
+            s,
+            names.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+fn get_name<T: Copy + PartialEq>(v: T, names: &[(&'static str, T)]) -> &'static str {
+    names.iter().find(|(_, x)| *x == v).map_or("other", |(n, _)| n)
+}
+
+impl Shape {
+    pub fn get_name(self) -> &'static str {
+        get_name(self, &SHAPE_NAMES)
+    }
+}
+
+impl Term {
+    pub fn get_name(self) -> &'static str {
+        get_name(self, &TERM_NAMES)
+    }
+}
+
+impl ModType {
+    pub fn get_name(self) -> &'static str {
+        get_name(self, &MODTYPE_NAMES)
+    }
+}
+
+impl Chem {
+    pub fn get_name(self) -> &'static str {
+        get_name(self, &CHEM_NAMES)
+    }
+
+    // the first chemistry named anywhere in free text: "RJ-LFP54173200", "LiFePO4 cell".
+    // a name counts when no letter touches either end of it.
+    pub fn find_in(text: &str) -> Option<Chem> {
+        let text = text.to_lowercase();
+        let letter_at = |i: usize| text[i..].chars().next().is_some_and(char::is_alphabetic);
+        let letter_before = |i: usize| {
+            text[..i]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphabetic)
+        };
+        CHEM_NAMES
+            .iter()
+            .filter(|(_, c)| *c != Chem::Other)
+            .filter_map(|(n, c)| {
+                text.match_indices(n)
+                    .map(|(i, _)| i)
+                    .find(|&i| !letter_before(i) && !letter_at(i + n.len()))
+                    .map(|i| (i, *c))
+            })
+            .min_by_key(|(i, _)| *i)
+            .map(|(_, c)| c)
+    }
+}
+
+impl<'de> Deserialize<'de> for Shape {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Shape, D::Error> {
+        from_name(d, "shape", &SHAPE_NAMES)
+    }
+}
+
+impl<'de> Deserialize<'de> for Term {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Term, D::Error> {
+        from_name(d, "termination", &TERM_NAMES)
+    }
+}
+
+impl<'de> Deserialize<'de> for ModType {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<ModType, D::Error> {
+        from_name(d, "input_type", &MODTYPE_NAMES)
+    }
+}
+
+impl<'de> Deserialize<'de> for Chem {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Chem, D::Error> {
+        from_name(d, "chemistry", &CHEM_NAMES)
+    }
+}
+
+impl Serialize for Shape {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.get_name())
+    }
+}
+
+impl Serialize for Term {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.get_name())
+    }
+}
+
+impl Serialize for ModType {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.get_name())
+    }
+}
+
+impl Serialize for Chem {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.get_name())
+    }
+}
+
 // the main cell/module data structure. gigafragile against addition/removal of fields.
 impl Module {
     // returns pack voltage in volts
     pub fn get_voltage(self) -> f32 {
-        return self.vnom;
+        self.vnom
     }
 
     // returns pack charge capacity in Ah
     pub fn get_ah(self) -> f32 {
-        return self.q;
+        self.q
     }
 
     // returns pack energy capacity in kWh
     pub fn get_kwh_nominal(self) -> f32 {
-        return self.get_ah() * self.get_voltage() / 1000_f32;
+        self.get_ah() * self.get_voltage() / 1000_f32
     }
 
     // returns cell count of module (it might be 4 cells welded together)
     pub fn get_cell_count(self) -> i32 {
-        return self.parallel * self.series;
-    }
-
-    // such math
-    pub fn print_possible_submodules(self) {
-        println!("Pack is {}S{}P", self.series, self.parallel);
-        println!("Modules in series for easier alignment/placement:");
-        for x in 1..MAX_SERIES_SUB {
-            if ((self.series % x) == 0) {
-                println!(
-                    "{} modules of {}S ({}/{}V) possible",
-                    x,
-                    self.series / x,
-                    (self.series / x) as f32 * 3.7,
-                    (self.series / x) as f32 * 4.1
-                );
-            }
-        }
-        println!("Modules in parallel for future expandability:");
-        for x in 1..MAX_PARALLEL_SUB {
-            if ((self.parallel % x) == 0) {
-                println!(
-                    "{} modules of {}P in parallel possible",
-                    x,
-                    self.parallel / x
-                );
-            }
-        }
+        self.parallel * self.series
     }
 
+
     // returns internal resistance of this unit as a function of state of charge.
     // arguments: soc f32 between {0,1}
     // formula is somewhat arbitrary right now, but looks approximately right.
     // parameters from measured data (fitted to some basis function) would be better.
     pub fn get_ir_dc(&self, soc: &f32) -> f32 {
-        let r = 1.0 / (4.5 * (0.1 * soc + 0.2)) * self.rnom;
-        return r;
+        1.0 / (4.5 * (0.1 * soc + 0.2)) * self.rnom
     }
 }
 
@@ -186,53 +337,51 @@ impl ModuleArray {
     pub fn get_topology(&self) -> (i32, i32) {
         let s = self.series * self.module.series;
         let p = self.parallel * self.module.parallel;
-        return (s, p);
-    }
-
-    // this isn't exactly user-facing, but it shouldn't be here.
-    pub fn print_topology(&self) {
-        let a = self.get_topology();
-        println!("ModuleArray topology: {}S{}P", a.0, a.1);
+        (s, p)
     }
 
     // returns pack voltage in V
     pub fn get_voltage(&self) -> f32 {
-        return self.module.vnom * self.series as f32;
+        self.module.vnom * self.series as f32
     }
 
     // returns pack charge capacity in Ah
     pub fn get_ah(&self) -> f32 {
-        return self.module.q * self.parallel as f32;
+        self.module.q * self.parallel as f32
     }
 
     // returns pack energy capacity in kWh
     pub fn get_kwh_nominal(&self) -> f32 {
-        return self.get_ah() * self.get_voltage() / 1000_f32;
+        self.get_ah() * self.get_voltage() / 1000_f32
     }
 
     pub fn get_module_count(&self) -> i32 {
         let a = self.series;
         let b = self.parallel;
-        return a * b;
+        a * b
     }
 
     pub fn get_cell_count(&self) -> i32 {
         let a = self.get_topology();
-        return a.0 * a.1;
+        a.0 * a.1
     }
 
     pub fn get_ir_dc(&self, soc: &f32) -> f32 {
-        let r = self.module.get_ir_dc(soc) * (self.series / self.parallel) as f32;
-        return r;
+        self.module.get_ir_dc(soc) * (self.series / self.parallel) as f32
     }
 }
 
 // most electric vehicle batteries can be modeled by this without any hacks.
+impl Default for Battery {
+    fn default() -> Battery {
+        Battery::new()
+    }
+}
+
 impl Battery {
     pub fn new() -> Battery {
-        let mut ma: Vec<ModuleArray> = Vec::new();
         Battery {
-            module_array: ma,
+            module_array: Vec::new(),
             e_params: None,
             m_params: None,
             t_params: None,
@@ -240,11 +389,8 @@ impl Battery {
     }
 
     pub fn new_from(m: Module, s: i32, p: i32) -> Battery {
-        let mut mav: Vec<ModuleArray> = Vec::new();
-        let mut ma = ModuleArray::new(m, s, p);
-        mav.push(ma);
         Battery {
-            module_array: mav,
+            module_array: vec![ModuleArray::new(m, s, p)],
             e_params: None,
             m_params: None,
             t_params: None,
@@ -264,13 +410,7 @@ impl Battery {
                 p = topo_i.1;
             }
         }
-        return (s, p);
-    }
-
-    pub fn print_topology(&self) {
-        let a = self.get_topology();
-        println!("{} {}S{}P", "Battery minimum topology: ".purple(), a.0, a.1);
-        println!("Note: this does not account for cell voltages.");
+        (s, p)
     }
 
     // returns pack voltage in V
@@ -280,11 +420,7 @@ impl Battery {
         for i in 0..l {
             v += self.module_array[i].get_voltage();
         }
-        return v;
-    }
-
-    pub fn print_voltage(&self) {
-        println!("Pack voltage: {}V", self.get_voltage());
+        v
     }
 
     // returns minimum pack charge capacity in Ah
@@ -297,16 +433,12 @@ impl Battery {
                 q = qi;
             }
         }
-        return q;
-    }
-
-    pub fn print_ah(&self) {
-        println!("Pack capacity: {}Ah", self.get_ah());
+        q
     }
 
     // returns pack minimum accessible energy capacity in kWh
     pub fn get_kwh_nominal(&self) -> f32 {
-        return self.get_ah() * self.get_voltage() / 1000_f32;
+        self.get_ah() * self.get_voltage() / 1000_f32
     }
 
     pub fn get_module_count(&self) -> i32 {
@@ -314,12 +446,12 @@ impl Battery {
         let b = self.module_array[0].get_topology();
         let x = a.0 / b.0;
         let y = a.1 / b.1;
-        return x * y;
+        x * y
     }
 
     pub fn get_cell_count(&self) -> i32 {
         let a = self.get_topology();
-        return a.0 * a.1;
+        a.0 * a.1
     }
 
     pub fn get_ir_dc(&self, soc: &f32) -> f32 {
@@ -327,13 +459,15 @@ impl Battery {
         for i in 0..self.module_array.len() {
             r += self.module_array[i].get_ir_dc(soc);
         }
-        return r;
+        r
     }
 }
 
 // returns vmin, vmax, vnom for a given chemistry.
 // hardcoding this table is a stopgap solution.
-pub fn defaults_from_chem(chem: &Chem) -> (f32, f32, f32, f32, f32, f32, f32, f32) {
+pub fn defaults_from_chem(
+    chem: &Chem,
+) -> (f32, f32, f32, f32, f32, f32, Temperature, Temperature) {
     let (vmin, vnom, vmax, cycle_life) = match chem {
         Chem::NMC => (3.0, 3.7, 4.2, 700.0),
         Chem::NCA => (3.0, 3.7, 4.3, 1000.0),
@@ -344,23 +478,67 @@ pub fn defaults_from_chem(chem: &Chem) -> (f32, f32, f32, f32, f32, f32, f32, f3
         Chem::Other => (0.0, 0.0, 0.0, 0.0),
     };
     // assume 2C maximum (dis)charge rate
-    let current_max_C = 2.0;
-    let (specific_heat, temp_max, temp_min) = (800.0, -20.0, 60.0);
-    return (
+    let current_max_c = 2.0;
+    let (specific_heat, temp_max, temp_min) = (
+        800.0,
+        Temperature::from_celsius(60.0),
+        Temperature::from_celsius(-20.0),
+    );
+    (
         vmin,
         vnom,
         vmax,
         cycle_life,
-        current_max_C,
+        current_max_c,
         specific_heat,
         temp_max,
         temp_min,
-    );
+    )
+}
+
+// entropic coefficient dU/dT of one cell in V/K, at SoC 0, 0.1, ... 1.0.
+// rough shapes from calorimetry papers; sign and magnitude matter more than detail.
+// LFP's flat voltage curve hides a large entropy change, so at low rates it can
+// make as much heat as the resistance does.
+fn entropic_table(chem: &Chem) -> [f32; 11] {
+    // mV/K
+    let mv = match chem {
+        Chem::LFP => [
+            0.15, 0.06, -0.01, -0.05, -0.07, -0.06, -0.04, -0.03, -0.025, -0.04, -0.06,
+        ],
+        Chem::NMC => [
+            -0.40, -0.25, -0.15, -0.10, -0.05, -0.02, 0.0, 0.02, 0.03, 0.02, 0.0,
+        ],
+        Chem::NCA => [
+            -0.35, -0.20, -0.12, -0.08, -0.05, -0.03, -0.01, 0.0, 0.01, 0.0, -0.01,
+        ],
+        Chem::LMO => [
+            -0.30, -0.20, -0.10, -0.05, 0.0, 0.05, 0.08, 0.10, 0.10, 0.05, 0.0,
+        ],
+        Chem::LTO => [
+            0.10, 0.08, 0.06, 0.05, 0.04, 0.04, 0.04, 0.05, 0.06, 0.08, 0.10,
+        ],
+        Chem::NiMH => [-0.40; 11],
+        Chem::Other => [0.0; 11],
+    };
+    let mut v = [0.0; 11];
+    for (x, m) in v.iter_mut().zip(mv.iter()) {
+        *x = m * 1e-3;
+    }
+    v
+}
+
+// dU/dT of one cell (V/K) at soc, interpolated
+pub fn entropic_coefficient(chem: &Chem, soc: f32) -> f32 {
+    let t = entropic_table(chem);
+    let x = soc.clamp(0.0, 1.0) * 10.0;
+    let i = (x.floor() as usize).min(9);
+    t[i] + (x - i as f32) * (t[i + 1] - t[i])
 }
 
 // Electrical parameters of the pack as a whole
-// unimplemented.
-#[derive(Copy, Clone, Debug)]
+// unimplemented, but carried in pack design files.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct ElectricalParams {
     // peak input or output current (A). compare to pack discharge expectations?
     // most packs can deliver more than their officially rated current...
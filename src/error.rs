@@ -0,0 +1,87 @@
+/*
+    everything that can go wrong loading or analyzing a pack. nothing below main.rs
+    prints or exits on failure; it hands one of these back and the caller decides.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    // a file (or a library cell or coolant named in one) couldn't be read or written
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    // the file was read but isn't a valid cell/pack/parameter file.
+    // (line, column) when the spot in the file is known.
+    Parse {
+        path: String,
+        pos: Option<(usize, usize)>,
+        message: String,
+    },
+    // the cell files parse but describe something that can't exist; every finding
+    // is kept (warnings too), with the module array it's about
+    Validation(Vec<(usize, Finding)>),
+    // the inputs are fine but the requested analysis has nothing to work with
+    Infeasible(String),
+    // a value that reads fine but can't be used: a command line option out of range,
+    // a table whose rows aren't in order
+    Invalid(String),
+}
+
+impl Error {
+    // for names that don't resolve to anything: unknown library cells, coolants...
+    pub fn not_found(path: &str, message: String) -> Error {
+        Error::Io {
+            path: path.to_string(),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, message),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Parse { path, pos, message } => match pos {
+                Some((line, col)) => write!(f, "{}:{}:{}: {}", path, line, col, message),
+                None if path.is_empty() => write!(f, "{}", message),
+                None => write!(f, "{}: {}", path, message),
+            },
+            Error::Validation(findings) => {
+                let errors = findings
+                    .iter()
+                    .filter(|(_, x)| x.severity == Severity::Error)
+                    .count();
+                write!(f, "{} error(s) in the cell files", errors)
+            }
+            Error::Infeasible(message) | Error::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
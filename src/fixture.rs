@@ -0,0 +1,324 @@
+/*
+    compression fixture design for prismatic (and pouch) cell stacks.
+    cells grow through their thickness with SoC and with age; end plates and
+    straps have to hold a stack pressure at BOL without crushing cells at EOL.
+    everything here is a 1D spring model along the stacking direction.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// stack pressure a cell tolerates when the cell file gives no max_pressure, as a
+// multiple of its recommended pressure
+const MAX_PRESSURE_FACTOR: f32 = 3.0;
+
+// optional cell file parameter describing thickness growth.
+// growth figures are fractions of the unconstrained cell thickness (dims[2]).
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Swelling {
+    // reversible growth from 0% to 100% SoC
+    pub soc_growth: f32,
+    // irreversible growth accumulated by end of life (80% SoH)
+    pub eol_growth: f32,
+    // effective through-thickness compressive modulus of the cell, Pa
+    pub modulus: f32,
+    // manufacturer's recommended stack pressure, Pa
+    pub pressure: f32,
+    // highest stack pressure the cell may see, Pa; MAX_PRESSURE_FACTOR x pressure if
+    // left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pressure: Option<f32>,
+}
+
+impl Swelling {
+    pub fn get_max_pressure(&self) -> f32 {
+        self.max_pressure.unwrap_or(MAX_PRESSURE_FACTOR * self.pressure)
+    }
+}
+
+// materials and constraints for end plates and straps
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+pub struct FixtureParams {
+    // end plate yield strength and Young's modulus, Pa
+    pub plate_yield: f32,
+    pub plate_modulus: f32,
+    // allowed end plate deflection at EOL, m
+    pub plate_max_deflection: f32,
+    // strap yield strength and Young's modulus, Pa
+    pub strap_yield: f32,
+    pub strap_modulus: f32,
+    // number of straps around the stack
+    pub strap_count: i32,
+    pub safety_factor: f32,
+}
+
+impl Default for FixtureParams {
+    // mild steel end plates, spring steel banding
+    fn default() -> FixtureParams {
+        FixtureParams {
+            plate_yield: 250e6,
+            plate_modulus: 200e9,
+            plate_max_deflection: 0.5e-3,
+            strap_yield: 350e6,
+            strap_modulus: 200e9,
+            strap_count: 4,
+            safety_factor: 1.5,
+        }
+    }
+}
+
+// stack state at one SoC / age point
+#[derive(Copy, Clone, Debug)]
+pub struct StackState {
+    pub soc: f32,
+    pub eol: bool,
+    // free (unconstrained) stack thickness, m
+    pub free_thickness: f32,
+    // force on the end plates, N
+    pub force: f32,
+    // resulting pressure on the cell faces, Pa
+    pub pressure: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct FixtureDesign {
+    pub cells: usize,
+    pub stack_thickness: f32,
+    pub preload: f32,
+    pub plate_thickness: f32,
+    // cross-section area of each strap, m2
+    pub strap_area: f32,
+    pub eol_force: f32,
+    // the cell's pressure limit, Pa, and the highest pressure any state reaches
+    pub max_pressure: f32,
+    pub peak_pressure: f32,
+    pub states: Vec<StackState>,
+}
+
+impl FixtureDesign {
+    // the fixture holds the stack too hard somewhere (typically EOL at full charge)
+    pub fn is_overpressure(&self) -> bool {
+        self.peak_pressure > self.max_pressure
+    }
+}
+
+impl Module {
+    // cell file values if given. otherwise prismatic and pouch LFP get typical
+    // large-format figures: ~1.2% reversible growth over SoC from the graphite anode,
+    // ~3.5% more by EOL, a 20 MPa effective stack modulus and 100 kPa recommended
+    // pressure. rough; a datasheet's own numbers belong in the cell file.
+    pub fn get_swelling(&self) -> Option<Swelling> {
+        if self.swelling.is_some() {
+            return self.swelling;
+        }
+        match (self.shape, self.chem) {
+            (Shape::Cylinder, _) => None,
+            (_, Chem::LFP) => Some(Swelling {
+                soc_growth: 0.012,
+                eol_growth: 0.035,
+                modulus: 20e6,
+                pressure: 100e3,
+                max_pressure: None,
+            }),
+            _ => None,
+        }
+    }
+
+    // face area the stack pressure acts on (height x width), m2
+    pub fn get_face_area(&self) -> f32 {
+        self.dims[0] * self.dims[1]
+    }
+
+    // sizes end plates and straps for a stack of n cells.
+    // end plates are treated as simply supported beams spanning the cell width between
+    // straps under uniform load; straps are plain tension members as long as the stack.
+    // the fixture and stack share the growth as two springs in series, so the sizing is
+    // iterated until plate and strap stiffness agree with the force they must carry.
+    pub fn design_fixture(&self, n: usize, fp: &FixtureParams) -> Result<FixtureDesign, Error> {
+        if let Shape::Cylinder = self.shape {
+            return Err(Error::Infeasible(
+                "no fixture to design: cylindrical cells don't need stack compression".to_string(),
+            ));
+        }
+        let sw = self.get_swelling().ok_or_else(|| {
+            Error::Infeasible(format!(
+                "no fixture to design: the cell file has no swelling data, and there's no \
+                 {:?} default",
+                self.chem
+            ))
+        })?;
+        let n = n.max(1);
+        let t = self.dims[2];
+        let area = self.get_face_area();
+        let (span, height) = (self.dims[1], self.dims[0]);
+        let stack = n as f32 * t;
+        let preload = sw.pressure * area;
+        let k_stack = sw.modulus * area / stack;
+        let straps = fp.strap_count.max(1) as f32;
+
+        let plate_for = |f: f32| -> f32 {
+            // bending: sigma = 6M / (h tp^2), M = F L / 8
+            let m = f * span / 8.0;
+            let t_strength = (6.0 * m * fp.safety_factor / (height * fp.plate_yield)).sqrt();
+            // deflection: 5 F L^3 / (384 E I), I = h tp^3 / 12
+            let t_stiff = (5.0 * f * span.powi(3) * 12.0
+                / (384.0 * fp.plate_modulus * height * fp.plate_max_deflection))
+                .cbrt();
+            t_strength.max(t_stiff)
+        };
+        let force_at = |growth: f32, tp: f32, a_strap: f32| -> f32 {
+            let k_strap = straps * fp.strap_modulus * a_strap / stack;
+            let i = height * tp.powi(3) / 12.0;
+            let k_plate = 384.0 * fp.plate_modulus * i / (5.0 * span.powi(3));
+            let k_fix = 1.0 / (1.0 / k_strap + 2.0 / k_plate);
+            preload + growth * k_stack * k_fix / (k_stack + k_fix)
+        };
+
+        let eol_growth = stack * (sw.eol_growth + sw.soc_growth);
+        let mut f_eol = preload;
+        let (mut tp, mut a_strap) = (0.0, 0.0);
+        for _ in 0..50 {
+            tp = plate_for(f_eol);
+            a_strap = f_eol * fp.safety_factor / (straps * fp.strap_yield);
+            let f = force_at(eol_growth, tp, a_strap);
+            if (f - f_eol).abs() < 1e-4 * f_eol {
+                f_eol = f;
+                break;
+            }
+            f_eol = f;
+        }
+        // final sizing must hold the converged force
+        tp = tp.max(plate_for(f_eol));
+        a_strap = a_strap.max(f_eol * fp.safety_factor / (straps * fp.strap_yield));
+
+        let mut states: Vec<StackState> = Vec::new();
+        for &eol in [false, true].iter() {
+            for &soc in [0.0_f32, 0.5, 1.0].iter() {
+                let growth = stack * (sw.soc_growth * soc + if eol { sw.eol_growth } else { 0.0 });
+                let force = force_at(growth, tp, a_strap);
+                states.push(StackState {
+                    soc,
+                    eol,
+                    free_thickness: stack + growth,
+                    force,
+                    pressure: force / area,
+                });
+            }
+        }
+
+        let peak_pressure = states.iter().map(|s| s.pressure).fold(0.0, f32::max);
+        Ok(FixtureDesign {
+            cells: n,
+            stack_thickness: stack,
+            preload,
+            plate_thickness: tp,
+            strap_area: a_strap,
+            eol_force: force_at(eol_growth, tp, a_strap),
+            max_pressure: sw.get_max_pressure(),
+            peak_pressure,
+            states,
+        })
+    }
+}
+
+impl Layout {
+    // longest run of cells standing side by side along the stacking (x) axis
+    pub fn get_max_stack(&self) -> usize {
+        let mut rows: Vec<(usize, f32, usize)> = Vec::new();
+        for c in &self.cells {
+            match rows
+                .iter_mut()
+                .find(|r| r.0 == c.array && (r.1 - c.pos[1]).abs() < 0.5 * c.size[1])
+            {
+                Some(r) => r.2 += 1,
+                None => rows.push((c.array, c.pos[1], 1)),
+            }
+        }
+        rows.iter().map(|r| r.2).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10 cells of 200 x 100 x 50 mm: a 0.5 m stack on 0.02 m2 faces
+    fn cell(max_pressure: f32) -> Module {
+        let mut m = read_cell("lfp_202ah", std::path::Path::new("")).unwrap();
+        m.dims = [0.2, 0.1, 0.05];
+        m.swelling = Some(Swelling {
+            soc_growth: 0.01,
+            eol_growth: 0.04,
+            modulus: 10e6,
+            pressure: 50e3,
+            max_pressure: Some(max_pressure),
+        });
+        m
+    }
+
+    // a fixture that can't stretch passes the whole growth strain to the cells:
+    // p = 50 kPa + 10 MPa x strain, so 150 kPa full at BOL and 550 kPa full at EOL
+    #[test]
+    fn rigid_fixture_pressure() {
+        let rigid = FixtureParams {
+            plate_modulus: 1e20,
+            strap_modulus: 1e20,
+            ..FixtureParams::default()
+        };
+        let d = cell(600e3).design_fixture(10, &rigid).unwrap();
+        assert!((d.stack_thickness - 0.5).abs() < 1e-6);
+        assert!((d.preload - 1000.0).abs() < 1e-2);
+        let pressure = |soc: f32, eol: bool| {
+            let s = d.states.iter().find(|s| s.soc == soc && s.eol == eol).unwrap();
+            s.pressure
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3 * b;
+        assert!(close(pressure(0.0, false), 50e3));
+        assert!(close(pressure(1.0, false), 150e3));
+        assert!(close(pressure(0.5, true), 500e3));
+        assert!(close(pressure(1.0, true), 550e3));
+        assert!(!d.is_overpressure());
+        assert!(cell(500e3).design_fixture(10, &rigid).unwrap().is_overpressure());
+    }
+
+    // steel that gives: the plates and straps are sized to the force they end up
+    // carrying, sigma = 6 (F L / 8) / (h tp^2) and 5 F L^3 / (384 E h tp^3 / 12), and
+    // the fixture's give takes pressure off the rigid case
+    #[test]
+    fn plates_and_straps_hold_eol_force() {
+        let fp = FixtureParams::default();
+        let d = cell(600e3).design_fixture(10, &fp).unwrap();
+        let (f, span, h, tp) = (d.eol_force, 0.1, 0.2, d.plate_thickness);
+        let stress = 6.0 * (f * span / 8.0) / (h * tp * tp);
+        assert!(stress * fp.safety_factor <= fp.plate_yield * 1.001);
+        let i = h * tp.powi(3) / 12.0;
+        let deflection = 5.0 * f * span.powi(3) / (384.0 * fp.plate_modulus * i);
+        assert!(deflection <= fp.plate_max_deflection * 1.001);
+        let strap_stress = f / (fp.strap_count as f32 * d.strap_area);
+        assert!(strap_stress * fp.safety_factor <= fp.strap_yield * 1.001);
+        assert!(d.eol_force / 0.02 < 550e3 && d.eol_force > d.preload);
+    }
+
+    #[test]
+    fn lfp_prisms_get_default_swelling() {
+        let lfp = read_cell("lfp_280ah", std::path::Path::new("")).unwrap();
+        assert_eq!(lfp.get_swelling().unwrap().pressure, 100e3);
+        assert_eq!(cell(600e3).get_swelling().unwrap().pressure, 50e3);
+        let cylinder = read_cell("tesla_21700", std::path::Path::new("")).unwrap();
+        assert!(cylinder.get_swelling().is_none());
+    }
+}
@@ -0,0 +1,377 @@
+/*
+    steady-state temperature spread across the packed layout with a cooling plate on
+    one face. every cell is split into layers along z and is isothermal within a layer;
+    layers conduct to each other through the cell, and to neighbouring cells through
+    the cell body and whatever fills the gap between them. faces without the plate
+    are taken as insulated, which is what makes the far cells run hot.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// layers each cell is split into along z
+const LAYERS: usize = 6;
+// cell-to-cell spread above this (K) ages the warm cells measurably faster
+pub const SPREAD_LIMIT: f32 = 5.0;
+// Gauss-Seidel stops when no node moves more than this fraction of the largest rise
+// above the plate. the solve runs on rises in f64: at ~300 K an f32 can't resolve a
+// fixed 1e-5 K step, so an absolute tolerance on temperatures never converges.
+const SOLVE_TOLERANCE: f64 = 1e-6;
+const SOLVE_ITERATIONS: usize = 50000;
+const OVER_RELAXATION: f64 = 1.8;
+
+// optional cell file parameter, W/m-K. in_plane runs along the electrode sheets (the
+// axis of a cylinder, the width and height of a prism or pouch), through_plane across
+// them (radially in a cylinder, through the thickness of a prism or pouch).
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Conductivity {
+    pub in_plane: f32,
+    pub through_plane: f32,
+}
+
+// which face of the cell block the plate is on. cells stand on z = 0, prisms stack along x.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum PlateFace {
+    Bottom,
+    Top,
+    // x = min and x = max
+    Left,
+    Right,
+    // y = min and y = max
+    Front,
+    Back,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GradientParams {
+    pub plate: PlateFace,
+    pub plate_temp: Temperature,
+    // thermal interface between cells and plate
+    pub tim_conductivity: f32,
+    pub tim_thickness: f32,
+    // whatever fills the gaps between neighbouring cells, W/m-K. touching cells are
+    // treated as a thin layer of it.
+    pub interface_conductivity: f32,
+    // pack current, A; the worst SoC and direction at that current is used
+    pub design_current: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ThermalGradient {
+    pub plate: PlateFace,
+    pub plate_temp: f32,
+    // W, whole layout
+    pub heat: f32,
+    // per placed cell, in layout order: mean over its layers, and hottest layer, K
+    pub cell_temps: Vec<f32>,
+    pub cell_peaks: Vec<f32>,
+    // cells touching the plate
+    pub cooled_cells: usize,
+    // time for the pack to settle from a cold start, s; from specific_heat
+    pub time_constant: f32,
+}
+
+impl Module {
+    // cell file values if given; otherwise typical jelly roll / stack values
+    pub fn get_conductivity(&self) -> Conductivity {
+        self.conductivity.unwrap_or(match self.shape {
+            Shape::Cylinder => Conductivity {
+                in_plane: 30.0,
+                through_plane: 0.8,
+            },
+            _ => Conductivity {
+                in_plane: 25.0,
+                through_plane: 0.8,
+            },
+        })
+    }
+}
+
+impl CellPlacement {
+    // W/m-K across the cell along x (0), y (1) or z (2)
+    fn get_conductivity(&self, axis: usize) -> f32 {
+        let k = self.module.get_conductivity();
+        match (self.module.shape, axis) {
+            (_, 2) => k.in_plane,
+            (Shape::Cylinder, _) => k.through_plane,
+            // prisms and pouches stack along x, so x crosses the sheets
+            (_, 0) => k.through_plane,
+            _ => k.in_plane,
+        }
+    }
+
+    // face area of one layer across `axis`, m2. a cylinder only touches its neighbours
+    // and a side plate along a strip, taken as a third of its diameter wide.
+    fn get_layer_face(&self, axis: usize) -> f32 {
+        let dz = self.size[2] / LAYERS as f32;
+        match (self.module.shape, axis) {
+            (Shape::Cylinder, 2) => 0.25 * PI * self.size[0] * self.size[0],
+            (Shape::Cylinder, _) => self.size[0] * dz / 3.0,
+            (_, 2) => self.size[0] * self.size[1],
+            (_, 0) => self.size[1] * dz,
+            _ => self.size[0] * dz,
+        }
+    }
+
+    // center of one layer to its face across `axis`, K/W
+    fn get_half_resistance(&self, axis: usize) -> f32 {
+        let half = if axis == 2 {
+            0.5 * self.size[2] / LAYERS as f32
+        } else {
+            0.5 * self.size[axis]
+        };
+        half / (self.get_conductivity(axis) * self.get_layer_face(axis)).max(1e-12)
+    }
+}
+
+impl Layout {
+    // node index of cell c, layer l is c * LAYERS + l; layer 0 sits at the bottom
+    pub fn solve_gradient(&self, gp: &GradientParams) -> Result<ThermalGradient, Error> {
+        let n = self.cells.len() * LAYERS;
+        if n == 0 {
+            return Err(Error::Infeasible(
+                "nothing to solve: the layout has no cells".to_string(),
+            ));
+        }
+        let sink = gp.plate_temp.kelvin();
+        let mut links: Vec<Vec<(usize, f32)>> = vec![Vec::new(); n];
+        let mut connect = |a: usize, b: usize, g: f32| {
+            links[a].push((b, g));
+            links[b].push((a, g));
+        };
+
+        for (c, cell) in self.cells.iter().enumerate() {
+            let g = 1.0 / (2.0 * cell.get_half_resistance(2));
+            for l in 1..LAYERS {
+                connect(c * LAYERS + l - 1, c * LAYERS + l, g);
+            }
+        }
+        // neighbours: the gap's conductance plus half of each cell body
+        for (a, b, s, _, _) in self.get_thermal_links(0.0) {
+            let (ca, cb) = (&self.cells[a], &self.cells[b]);
+            let axis = if (ca.pos[0] - cb.pos[0]).abs() - 0.5 * (ca.size[0] + cb.size[0])
+                > (ca.pos[1] - cb.pos[1]).abs() - 0.5 * (ca.size[1] + cb.size[1])
+            {
+                0
+            } else {
+                1
+            };
+            let r_gap = LAYERS as f32 / (gp.interface_conductivity * s).max(1e-12);
+            let g = 1.0 / (r_gap + ca.get_half_resistance(axis) + cb.get_half_resistance(axis));
+            for l in 0..LAYERS {
+                connect(a * LAYERS + l, b * LAYERS + l, g);
+            }
+        }
+
+        // plate: every cell whose face lies on the chosen side of the block
+        let (lo, hi) = self.get_bounds();
+        let (axis, top) = match gp.plate {
+            PlateFace::Bottom => (2, false),
+            PlateFace::Top => (2, true),
+            PlateFace::Left => (0, false),
+            PlateFace::Right => (0, true),
+            PlateFace::Front => (1, false),
+            PlateFace::Back => (1, true),
+        };
+        let mut to_plate = vec![0.0_f32; n];
+        let mut cooled_cells = 0;
+        for (c, cell) in self.cells.iter().enumerate() {
+            let face = if top {
+                cell.pos[axis] + 0.5 * cell.size[axis]
+            } else {
+                cell.pos[axis] - 0.5 * cell.size[axis]
+            };
+            let edge = if top { hi[axis] } else { lo[axis] };
+            if (face - edge).abs() > 0.25 * cell.size[axis] {
+                continue;
+            }
+            cooled_cells += 1;
+            let area = cell.get_layer_face(axis);
+            let g = 1.0
+                / (gp.tim_thickness / (gp.tim_conductivity * area).max(1e-12)
+                    + cell.get_half_resistance(axis));
+            match (axis, top) {
+                (2, false) => to_plate[c * LAYERS] = g,
+                (2, true) => to_plate[c * LAYERS + LAYERS - 1] = g,
+                _ => {
+                    for l in 0..LAYERS {
+                        to_plate[c * LAYERS + l] = g;
+                    }
+                }
+            }
+        }
+        if cooled_cells == 0 {
+            return Err(Error::Infeasible(
+                "nothing to solve: no cells touch the plate".to_string(),
+            ));
+        }
+
+        let heat: Vec<f32> = self
+            .cells
+            .iter()
+            .map(|c| {
+                let ma = c.module;
+                let parallel = self
+                    .cells
+                    .iter()
+                    .filter(|o| o.group == c.group)
+                    .count()
+                    .max(1) as f32;
+                ma.get_design_heat(gp.design_current / parallel, sink) / LAYERS as f32
+            })
+            .collect();
+
+        // successive over-relaxation on the rise above the plate; the network is
+        // diagonally dominant once one node sees the plate, so this converges
+        let g_sum: Vec<f64> = (0..n)
+            .map(|i| (to_plate[i] + links[i].iter().map(|l| l.1).sum::<f32>()) as f64)
+            .collect();
+        let mut rise = vec![0.0_f64; n];
+        let mut converged = false;
+        for _ in 0..SOLVE_ITERATIONS {
+            let (mut change, mut largest) = (0.0_f64, 0.0_f64);
+            for i in 0..n {
+                let mut flow = heat[i / LAYERS] as f64;
+                for &(j, g) in &links[i] {
+                    flow += g as f64 * rise[j];
+                }
+                let r = rise[i] + OVER_RELAXATION * (flow / g_sum[i].max(1e-12) - rise[i]);
+                change = change.max((r - rise[i]).abs());
+                largest = largest.max(r.abs());
+                rise[i] = r;
+            }
+            if change <= SOLVE_TOLERANCE * largest {
+                converged = true;
+                break;
+            }
+        }
+        if !converged {
+            return Err(Error::Infeasible(format!(
+                "temperature spread didn't converge in {} iterations",
+                SOLVE_ITERATIONS
+            )));
+        }
+        let temp: Vec<f32> = rise.iter().map(|r| sink + *r as f32).collect();
+
+        let cell_temps: Vec<f32> = temp
+            .chunks(LAYERS)
+            .map(|t| t.iter().sum::<f32>() / LAYERS as f32)
+            .collect();
+        let cell_peaks: Vec<f32> = temp
+            .chunks(LAYERS)
+            .map(|t| t.iter().cloned().fold(f32::MIN, f32::max))
+            .collect();
+        let capacity: f32 = self
+            .cells
+            .iter()
+            .map(|c| c.module.get_heat_capacity())
+            .sum();
+        let total_heat = heat.iter().sum::<f32>() * LAYERS as f32;
+        // the pack settles toward its mean rise over one lumped time constant
+        let mean_rise = cell_temps.iter().map(|t| t - sink).sum::<f32>() / self.cells.len() as f32;
+        Ok(ThermalGradient {
+            plate: gp.plate,
+            plate_temp: sink,
+            heat: total_heat,
+            cell_temps,
+            cell_peaks,
+            cooled_cells,
+            time_constant: if total_heat > 0.0 {
+                capacity * mean_rise / total_heat
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+impl ThermalGradient {
+    // (coolest, hottest) cell by mean temperature
+    pub fn get_extremes(&self) -> (usize, usize) {
+        let mut lo = 0;
+        let mut hi = 0;
+        for (i, &t) in self.cell_temps.iter().enumerate() {
+            if t < self.cell_temps[lo] {
+                lo = i;
+            }
+            if t > self.cell_temps[hi] {
+                hi = i;
+            }
+        }
+        (lo, hi)
+    }
+
+    // hottest cell minus coolest cell, K
+    pub fn get_spread(&self) -> f32 {
+        let (lo, hi) = self.get_extremes();
+        self.cell_temps[hi] - self.cell_temps[lo]
+    }
+
+    // largest difference between a cell's hottest layer and its mean, K
+    pub fn get_internal_spread(&self) -> f32 {
+        self.cell_peaks
+            .iter()
+            .zip(&self.cell_temps)
+            .map(|(p, t)| p - t)
+            .fold(0.0, f32::max)
+    }
+}
+
+impl Battery {
+    pub fn analyze_gradient(&self, gp: &GradientParams) -> Result<ThermalGradient, Error> {
+        self.get_layout().solve_gradient(gp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(plate: PlateFace) -> GradientParams {
+        GradientParams {
+            plate,
+            plate_temp: Temperature::from_celsius(25.0),
+            tim_conductivity: 3.0,
+            tim_thickness: 0.001,
+            interface_conductivity: 0.1,
+            design_current: 150.0,
+        }
+    }
+
+    // a 960-cell pack meets the relative tolerance inside SOLVE_ITERATIONS, and every
+    // cell ends up above the plate it's cooled by
+    #[test]
+    fn solve_converges_on_large_packs() {
+        let cell = read_cell("tesla_21700", std::path::Path::new("")).unwrap();
+        let g = Battery::new_from(cell, 96, 10)
+            .analyze_gradient(&params(PlateFace::Bottom))
+            .unwrap();
+        assert!(g.cell_temps.iter().all(|t| *t > g.plate_temp));
+    }
+
+    // cooled from one side, the cells next to the plate have to be the coolest
+    #[test]
+    fn side_plate_cools_nearest_cells() {
+        let cell = read_cell("lfp_202ah", std::path::Path::new("")).unwrap();
+        let b = Battery::new_from(cell, 16, 1);
+        let g = b.analyze_gradient(&params(PlateFace::Left)).unwrap();
+        let (lo, hi) = g.get_extremes();
+        let layout = b.get_layout();
+        assert!(layout.cells[lo].pos[0] < layout.cells[hi].pos[0]);
+        assert!(g.get_spread() > 0.0);
+    }
+}
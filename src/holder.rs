@@ -0,0 +1,146 @@
+/*
+    cylindrical cell holders: the plastic plates that grip the ends of cylindrical
+    cells and set their spacing. the holder decides the pitch (and so the packed
+    volume), and adds its own size and mass to the pack.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// how neighbouring columns of cylinders sit relative to each other
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Arrangement {
+    // odd columns shifted half a pitch so cells nest
+    Hex,
+    // straight rows and columns
+    Square,
+}
+
+// optional cell file parameter; cylinders only.
+// without one, cylinders are packed touching in a hex arrangement and no holder is modeled.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CellHolder {
+    pub arrangement: Arrangement,
+    // gap between neighbouring cells (holder web thickness), m
+    pub gap: f32,
+    // outer wall thickness around the cell block, m
+    pub wall: f32,
+    // how far each of the two plates (top and bottom) reaches along the cell, m
+    pub depth: f32,
+    // holder material density, kg/m3
+    pub density: f32,
+}
+
+impl Default for CellHolder {
+    // injection molded polycarbonate, typical of off-the-shelf 18650/21700 holders
+    fn default() -> CellHolder {
+        CellHolder {
+            arrangement: Arrangement::Hex,
+            gap: 1.5e-3,
+            wall: 3e-3,
+            depth: 8e-3,
+            density: 1200.0,
+        }
+    }
+}
+
+// one pair of holder plates around one module array
+#[derive(Clone, Debug)]
+pub struct HolderDesign {
+    pub array: usize,
+    pub cells: usize,
+    pub arrangement: Arrangement,
+    // outer size of a plate (x, y, z), m
+    pub plate_size: [f32; 3],
+    // center of the bottom plate; the top plate sits at the other end of the cells
+    pub bottom_pos: [f32; 3],
+    pub top_pos: [f32; 3],
+    // both plates together, kg
+    pub mass: f32,
+}
+
+impl Module {
+    // hex unless a holder says otherwise
+    pub fn get_arrangement(&self) -> Arrangement {
+        self.holder.map_or(Arrangement::Hex, |h| h.arrangement)
+    }
+
+    // center-to-center distance between touching-or-nearly neighbours, m
+    pub fn get_cell_spacing(&self) -> f32 {
+        self.dims[0] + self.holder.map_or(0.0, |h| h.gap)
+    }
+}
+
+impl Layout {
+    // holder plates for every module array of cylinders that has a holder
+    pub fn get_holders(&self) -> Vec<HolderDesign> {
+        let mut out: Vec<HolderDesign> = Vec::new();
+        let arrays = self.cells.iter().map(|c| c.array + 1).max().unwrap_or(0);
+        for a in 0..arrays {
+            let cells: Vec<&CellPlacement> = self.cells.iter().filter(|c| c.array == a).collect();
+            let (m, h) = match cells.first() {
+                Some(c) => match (c.module.shape, c.module.holder) {
+                    (Shape::Cylinder, Some(h)) => (c.module, h),
+                    _ => continue,
+                },
+                None => continue,
+            };
+            let mut lo = [f32::MAX; 2];
+            let mut hi = [f32::MIN; 2];
+            for c in &cells {
+                for k in 0..2 {
+                    lo[k] = lo[k].min(c.pos[k] - 0.5 * c.size[k]);
+                    hi[k] = hi[k].max(c.pos[k] + 0.5 * c.size[k]);
+                }
+            }
+            let margin = 0.5 * h.gap + h.wall;
+            let size = [
+                hi[0] - lo[0] + 2.0 * margin,
+                hi[1] - lo[1] + 2.0 * margin,
+                h.depth,
+            ];
+            // plates are solid apart from one through-hole per cell
+            let hole = PI * 0.25 * m.dims[0] * m.dims[0] * h.depth;
+            let plate = size[0] * size[1] * size[2] - cells.len() as f32 * hole;
+            let (cx, cy) = (0.5 * (lo[0] + hi[0]), 0.5 * (lo[1] + hi[1]));
+            out.push(HolderDesign {
+                array: a,
+                cells: cells.len(),
+                arrangement: h.arrangement,
+                plate_size: size,
+                bottom_pos: [cx, cy, 0.5 * h.depth],
+                top_pos: [cx, cy, m.dims[1] - 0.5 * h.depth],
+                mass: 2.0 * plate.max(0.0) * h.density,
+            });
+        }
+        out
+    }
+
+    // adds holder plates as components so mass properties and CAD pick them up
+    pub fn add_holders(&mut self) {
+        for h in self.get_holders() {
+            for (name, pos) in [("bottom", h.bottom_pos), ("top", h.top_pos)].iter() {
+                self.add_component(Component {
+                    name: format!("holder {} {}", h.array, name),
+                    mass: 0.5 * h.mass,
+                    pos: *pos,
+                    size: h.plate_size,
+                });
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 /*
-    i/o abstraction layer for reading human-readable cell parameter files
+    i/o abstraction layer for reading human-readable cell parameter files.
+    RON, JSON, TOML and YAML all hold the same fields; the extension picks the format.
     unimplemented: writing or exporting reports or 3D models (i.e. via openscad)
 
     Copyright (C) 2020 Paul Hansel
@@ -21,21 +22,22 @@
 use crate::*;
 
 extern crate ron;
-use ron::de::from_reader;
-use std::fs::File;
+use ron::ser::{PrettyConfig, Serializer};
+use serde::de::DeserializeOwned;
+use std::path::Path;
 
-// struct purely for reading input RON files
-#[derive(Clone, Debug, Deserialize)]
+// struct for reading and writing cell RON files
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct InputModule {
     // these are necessary for basic use
-    shape: String,
-    input_type: String,
-    chem: String,
+    shape: Shape,
+    input_type: ModType,
+    chem: Chem,
     series: i32,
     parallel: i32,
     dims: [f32; 3],
     mass: f32,
-    termination: String,
+    termination: Term,
     vmin: f32,
     vmax: f32,
     // nominal voltage
@@ -46,58 +48,449 @@ pub struct InputModule {
     rnom: f32,
     // continuous maximum current according to manufacturer
     max_current_continuous: f32,
-    // specific heat
-    // kJ/kg-K or J/g-k (equal)
+    // specific heat, J/kg-K (typically 800-1100 for cells)
     specific_heat: f32,
     // to 80% SoH at 1C charge/discharge
     cycle_life: f32,
-    // KELVIN
-    temp_max: f32,
-    temp_min: f32,
-}
-
-// call these with io::read_module(bar) etc.
-pub fn read_module(filename: &str) -> Module {
-    let f = File::open(filename).expect("Failed to open file.");
-    let input: InputModule = match from_reader(f) {
-        Ok(x) => x,
-        Err(e) => {
-            println!("Failed to load input file: {}", e);
-            std::process::exit(1);
+    // KELVIN as a bare number, or with units: "60 C", "333.15 K"
+    temp_max: Temperature,
+    temp_min: Temperature,
+    // optional; charging window defaults to 0-45 C, storage to temp_min..temp_max
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temp_charge_min: Option<Temperature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temp_charge_max: Option<Temperature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temp_storage_min: Option<Temperature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temp_storage_max: Option<Temperature>,
+    // optional; prismatic and pouch cells only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    swelling: Option<Swelling>,
+    // optional; meters
+    #[serde(default)]
+    thickness_tolerance: f32,
+    // optional; m3, for custom shapes where dims is only the bounding box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume: Option<f32>,
+    // optional; cylinders only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    holder: Option<CellHolder>,
+    // optional; chemistry defaults otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    runaway: Option<Runaway>,
+    // optional; W/m-K along and across the electrode sheets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    conductivity: Option<Conductivity>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Ron,
+    Json,
+    Toml,
+    Yaml,
+}
+
+// names and file extensions; the first for each format is the one written
+const FORMAT_NAMES: [(&str, Format); 5] = [
+    ("ron", Format::Ron),
+    ("json", Format::Json),
+    ("toml", Format::Toml),
+    ("yaml", Format::Yaml),
+    ("yml", Format::Yaml),
+];
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        FORMAT_NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, f)| *f)
+    }
+
+    // None if the extension isn't one of the formats
+    pub fn from_path(filename: &str) -> Option<Format> {
+        Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(Format::from_name)
+    }
+
+    // the extension if it names a format, then the fallback, then RON like every
+    // older file
+    pub fn resolve(filename: &str, fallback: Option<Format>) -> Format {
+        Format::from_path(filename)
+            .or(fallback)
+            .unwrap_or(Format::Ron)
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        FORMAT_NAMES
+            .iter()
+            .find(|(_, f)| f == self)
+            .map_or("ron", |(n, _)| n)
+    }
+}
+
+// call these with io::read_module(bar)? etc.
+pub fn read_module(filename: &str) -> Result<Module, Error> {
+    read_module_as(filename, Format::resolve(filename, None))
+}
+
+pub fn read_module_as(filename: &str, format: Format) -> Result<Module, Error> {
+    let input: InputModule = read_file_as(filename, format)?;
+    Ok(io::input_module_to_module(input))
+}
+
+// every file battgen reads goes through here
+fn read_file<T: DeserializeOwned>(filename: &str) -> Result<T, Error> {
+    read_file_as(filename, Format::resolve(filename, None))
+}
+
+fn read_file_as<T: DeserializeOwned>(filename: &str, format: Format) -> Result<T, Error> {
+    let text = std::fs::read_to_string(filename).map_err(|e| Error::Io {
+        path: filename.to_string(),
+        source: e,
+    })?;
+    parse_text_as(&text, filename, format)
+}
+
+// `filename` only names the text in errors
+fn parse_text_as<T: DeserializeOwned>(text: &str, filename: &str, format: Format) -> Result<T, Error> {
+    // (position if the parser knows it, message)
+    let parsed: Result<T, (Option<(usize, usize)>, String)> = match format {
+        Format::Ron => ron::de::from_str(text).map_err(|e| match e {
+            // ron puts the position in front of syntax errors itself
+            ron::de::Error::Parser(_, p) => (
+                Some((p.line, p.col)),
+                e.to_string()
+                    .trim_start_matches(&format!("{}: ", p))
+                    .to_string(),
+            ),
+            _ => (None, e.to_string()),
+        }),
+        Format::Json => serde_json::from_str(text).map_err(|e| {
+            let pos = Some((e.line(), e.column())).filter(|p| p.0 > 0);
+            (pos, strip_location(&e.to_string()))
+        }),
+        Format::Toml => toml::from_str(text).map_err(|e| {
+            let pos = e.span().map(|s| get_line_col(text, s.start));
+            (pos, e.message().trim().replace('\n', "; "))
+        }),
+        Format::Yaml => serde_yaml::from_str(text).map_err(|e| {
+            let pos = e.location().map(|l| (l.line(), l.column()));
+            (pos, strip_location(&e.to_string()))
+        }),
+    };
+    // a value the message names is the most precise spot; yaml in particular only
+    // knows which mapping a bad value was in
+    parsed.map_err(|(pos, message)| Error::Parse {
+        path: filename.to_string(),
+        pos: find_quoted(text, &message).or(pos),
+        message,
+    })
+}
+
+// json and yaml tack " at line L column C" onto their messages
+fn strip_location(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message.to_string(),
+    }
+}
+
+// 1-based line and column of a byte offset
+fn get_line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+// parsers don't always know where a bad value (unknown chemistry, unreadable
+// temperature...) was; the quoted value from the message is looked up in the file,
+// quoted or, as yaml allows, bare after its key
+fn find_quoted(text: &str, message: &str) -> Option<(usize, usize)> {
+    let value = message.split('"').nth(1).filter(|v| !v.trim().is_empty())?;
+    let forms = [
+        (format!("\"{}\"", value), 0),
+        (format!("'{}'", value), 0),
+        (format!(": {}", value), 2),
+    ];
+    forms.iter().find_map(|(form, skip)| {
+        text.lines().enumerate().find_map(|(i, l)| {
+            l.find(form.as_str())
+                .filter(|c| skip == &0 || l[c + form.len()..].trim().is_empty())
+                .map(|c| (i + 1, c + skip + 1))
+        })
+    })
+}
+
+// toml widens every f32 to f64, so 0.205 would be written as 0.20499999821186066.
+// all of battgen's numbers are f32, so the shortest f32 form loses nothing.
+fn shorten_floats(v: toml::Value) -> toml::Value {
+    match v {
+        toml::Value::Float(f) => toml::Value::Float((f as f32).to_string().parse().unwrap_or(f)),
+        toml::Value::Array(a) => toml::Value::Array(a.into_iter().map(shorten_floats).collect()),
+        toml::Value::Table(t) => {
+            toml::Value::Table(t.into_iter().map(|(k, x)| (k, shorten_floats(x))).collect())
+        }
+        x => x,
+    }
+}
+
+fn write_file_as<T: Serialize>(value: &T, filename: &str, format: Format) -> Result<(), Error> {
+    std::fs::write(filename, to_text(value, format, filename)?).map_err(|e| Error::Io {
+        path: filename.to_string(),
+        source: e,
+    })
+}
+
+// `filename` only names the output in errors; a value the format can't hold fails like
+// a write would
+fn to_text<T: Serialize>(value: &T, format: Format, filename: &str) -> Result<String, Error> {
+    let text = match format {
+        Format::Ron => {
+            let mut ser = Serializer::new(Some(PrettyConfig::default()), true);
+            value
+                .serialize(&mut ser)
+                .map(|_| ser.into_output_string() + "\n")
+                .map_err(|e| e.to_string())
         }
+        Format::Json => serde_json::to_string_pretty(value)
+            .map(|s| s + "\n")
+            .map_err(|e| e.to_string()),
+        Format::Toml => toml::Value::try_from(value)
+            .and_then(|v| toml::to_string_pretty(&shorten_floats(v)))
+            .map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
     };
-    return io::input_module_to_module(input);
+    text.map_err(|e| Error::Io {
+        path: filename.to_string(),
+        source: std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("can't be written as {}: {}", format.get_name(), e),
+        ),
+    })
 }
 
 // accepts and destroys input module to create full module
-fn input_module_to_module(m: InputModule) -> Module {
-    return Module {
-        shape: match m.shape.as_str() {
-            "cylinder" => Shape::Cylinder,
-            "prism" => Shape::Prism,
-            _ => Shape::Other,
-        },
-        input_type: match m.input_type.as_str() {
-            "cell" => ModType::Cell,
-            "module" => ModType::Module,
-            _ => ModType::Module,
-        },
-        chem: match m.chem.to_lowercase().as_str() {
-            "lmo" => Chem::LMO,
-            "nmc" => Chem::NMC,
-            "nca" => Chem::NCA,
-            "lfp" | "LiFePO4" => Chem::LFP,
-            _ => Chem::Other,
-        },
+pub fn input_module_to_module(m: InputModule) -> Module {
+    Module {
+        shape: m.shape,
+        input_type: m.input_type,
+        chem: m.chem,
+        series: m.series,
+        parallel: m.parallel,
+        dims: m.dims,
+        mass: m.mass,
+        termination: m.termination,
+        vmin: m.vmin,
+        vmax: m.vmax,
+        vnom: m.vnom,
+        q: m.q,
+        rnom: m.rnom,
+        max_current_continuous: m.max_current_continuous,
+        specific_heat: m.specific_heat,
+        cycle_life: m.cycle_life,
+        temp_max: m.temp_max,
+        temp_min: m.temp_min,
+        temp_charge_min: m.temp_charge_min,
+        temp_charge_max: m.temp_charge_max,
+        temp_storage_min: m.temp_storage_min,
+        temp_storage_max: m.temp_storage_max,
+        swelling: m.swelling,
+        thickness_tolerance: m.thickness_tolerance,
+        volume: m.volume,
+        holder: m.holder,
+        runaway: m.runaway,
+        conductivity: m.conductivity,
+    }
+}
+
+// user-defined coolant with its own property table, see Coolant
+// the table must have at least one row and one row per temperature; rows may come
+// in any order, get_state wants them sorted
+pub fn read_coolant(filename: &str) -> Result<Coolant, Error> {
+    let mut c: Coolant = read_file(filename)?;
+    if c.table.is_empty() {
+        return Err(Error::Invalid(format!("{}: coolant table is empty", filename)));
+    }
+    if let Some(r) = c.table.iter().find(|r| !r.temp.is_finite()) {
+        return Err(Error::Invalid(format!(
+            "{}: coolant table temperature {} is not a number",
+            filename, r.temp
+        )));
+    }
+    c.table.sort_by(|a, b| a.temp.total_cmp(&b.temp));
+    if let Some(w) = c.table.windows(2).find(|w| w[0].temp == w[1].temp) {
+        return Err(Error::Invalid(format!(
+            "{}: coolant table has two rows at {} K",
+            filename, w[0].temp
+        )));
+    }
+    Ok(c)
+}
+
+// thermal system file; same as ThermalParams, but the coolant is a library name
+// (see Coolant::from_name) or a path to a coolant RON file
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InputThermalParams {
+    coolant: String,
+    // m3/s; optional, sized from coolant_rise if left out
+    #[serde(default)]
+    flow_rate: f32,
+    // W/m2-K
+    heat_k: f32,
+    // KELVIN
+    inlet_temp: f32,
+    cell_temp_max: f32,
+    coolant_rise: f32,
+    // A
+    design_current: f32,
+}
+
+pub fn read_thermal_params(filename: &str) -> Result<ThermalParams, Error> {
+    input_thermal_to_thermal(read_input_thermal_params(filename)?)
+}
+
+// the file as written, for carrying into a pack design
+pub fn read_input_thermal_params(filename: &str) -> Result<InputThermalParams, Error> {
+    read_file(filename)
+}
+
+fn input_thermal_to_thermal(input: InputThermalParams) -> Result<ThermalParams, Error> {
+    let coolant = if Format::from_path(&input.coolant).is_some() {
+        read_coolant(&input.coolant)?
+    } else {
+        match Coolant::from_name(&input.coolant) {
+            Some(c) => c,
+            None => {
+                return Err(Error::not_found(
+                    &input.coolant,
+                    "unknown coolant (see --coolants)".to_string(),
+                ))
+            }
+        }
+    };
+    Ok(ThermalParams {
+        coolant,
+        flow_rate: input.flow_rate,
+        heat_k: input.heat_k,
+        inlet_temp: input.inlet_temp,
+        cell_temp_max: input.cell_temp_max,
+        coolant_rise: input.coolant_rise,
+        design_current: input.design_current,
+    })
+}
+
+// fan curve, gap and ambient for air-cooled packs, see AirCoolingParams
+pub fn read_air_cooling_params(filename: &str) -> Result<AirCoolingParams, Error> {
+    read_file(filename)
+}
+
+// ambient, insulation and heater for cold-weather preheat, see PreheatParams
+pub fn read_preheat_params(filename: &str) -> Result<PreheatParams, Error> {
+    read_file(filename)
+}
+
+// cooling plate face, interface materials and load, see GradientParams
+pub fn read_gradient_params(filename: &str) -> Result<GradientParams, Error> {
+    read_file(filename)
+}
+
+// measured or specified module temperatures, see TemperatureProfile
+pub fn read_temperature_profile(filename: &str) -> Result<TemperatureProfile, Error> {
+    read_file(filename)
+}
+
+// drive cycle or charge profile plus heat path, see CurrentProfile
+pub fn read_profile(filename: &str) -> Result<CurrentProfile, Error> {
+    read_file(filename)
+}
+
+// mounting capacities and design accelerations, see MechanicalParams
+pub fn read_mechanical_params(filename: &str) -> Result<MechanicalParams, Error> {
+    read_file(filename)
+}
+
+// vendor datasheet text, see parse_datasheet
+pub fn read_datasheet(filename: &str) -> Result<Vec<ImportedCell>, Error> {
+    let text = std::fs::read_to_string(filename).map_err(|e| Error::Io {
+        path: filename.to_string(),
+        source: e,
+    })?;
+    let cells = parse_datasheet(&text);
+    if cells.is_empty() {
+        return Err(Error::Parse {
+            path: filename.to_string(),
+            pos: None,
+            message: "no cells found; a cell needs at least a capacity (50Ah)".to_string(),
+        });
+    }
+    Ok(cells)
+}
+
+// a cell file for an imported cell, headed by a comment listing what the sheet didn't
+// give. json has no comments; there the list is only printed. an existing file is
+// only replaced when `overwrite` is set.
+pub fn write_imported_cell(
+    cell: &ImportedCell,
+    filename: &str,
+    format: Format,
+    overwrite: bool,
+) -> Result<(), Error> {
+    if !overwrite && std::path::Path::new(filename).exists() {
+        return Err(Error::Io {
+            path: filename.to_string(),
+            source: std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "already exists, not overwritten",
+            ),
+        });
+    }
+    let mut text = String::new();
+    let comment = match format {
+        Format::Ron => Some("//"),
+        Format::Toml | Format::Yaml => Some("#"),
+        Format::Json => None,
+    };
+    if let Some(c) = comment {
+        if !cell.source.is_empty() {
+            text += &format!("{} imported from datasheet row: {}\n", c, cell.source);
+        }
+        let defaulted = cell.get_defaulted();
+        if !defaulted.is_empty() {
+            let fields = defaulted.join(", ");
+            text += &format!("{} not on the datasheet, defaults used: {}\n", c, fields);
+        }
+        let zeroed = cell.get_zeroed();
+        if !zeroed.is_empty() {
+            let fields = zeroed.join(", ");
+            text += &format!("{} not on the datasheet, set to 0; fill in: {}\n", c, fields);
+        }
+    }
+    text += &to_text(&module_to_input_module(&cell.module), format, filename)?;
+    std::fs::write(filename, text).map_err(|e| Error::Io {
+        path: filename.to_string(),
+        source: e,
+    })
+}
+
+// the reverse of input_module_to_module
+pub fn module_to_input_module(m: &Module) -> InputModule {
+    InputModule {
+        shape: m.shape,
+        input_type: m.input_type,
+        chem: m.chem,
         series: m.series,
         parallel: m.parallel,
         dims: m.dims,
         mass: m.mass,
-        termination: match m.termination.as_str() {
-            "axial" => Term::Axial,
-            "end" => Term::End,
-            _ => Term::Other,
-        },
+        termination: m.termination,
         vmin: m.vmin,
         vmax: m.vmax,
         vnom: m.vnom,
@@ -108,10 +501,315 @@ fn input_module_to_module(m: InputModule) -> Module {
         cycle_life: m.cycle_life,
         temp_max: m.temp_max,
         temp_min: m.temp_min,
+        temp_charge_min: m.temp_charge_min,
+        temp_charge_max: m.temp_charge_max,
+        temp_storage_min: m.temp_storage_min,
+        temp_storage_max: m.temp_storage_max,
+        swelling: m.swelling,
+        thickness_tolerance: m.thickness_tolerance,
+        volume: m.volume,
+        holder: m.holder,
+        runaway: m.runaway,
+        conductivity: m.conductivity,
+    }
+}
+
+// cell file text; read_module on it gives back the same values.
+// comments and layout of a hand-written file aren't kept.
+pub fn module_to_ron(module: &InputModule) -> Result<String, Error> {
+    to_text(module, Format::Ron, "cell file")
+}
+
+pub fn module_to_text(module: &InputModule, format: Format) -> Result<String, Error> {
+    to_text(module, format, "cell file")
+}
+
+// saves a cell file, e.g. io::write_module(&io::module_to_input_module(&m), "cell.json")
+pub fn write_module(module: &InputModule, filename: &str) -> Result<(), Error> {
+    write_module_as(module, filename, Format::resolve(filename, None))
+}
+
+pub fn write_module_as(module: &InputModule, filename: &str, format: Format) -> Result<(), Error> {
+    write_file_as(module, filename, format)
+}
+
+// pack design file: everything needed to rebuild a Battery, so designs can live in git
+// instead of on the command line
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InputPack {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    // in series, in order
+    pub arrays: Vec<InputModuleArray>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub electrical: Option<ElectricalParams>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mechanical: Option<MechanicalParams>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<InputThermalParams>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InputModuleArray {
+    // library ID (see cell_library) or a path to a cell file in any format, relative
+    // to the design file
+    pub cell: String,
+    pub series: i32,
+    pub parallel: i32,
+}
+
+// the cells in examples/cells are built into the binary, so library IDs work wherever
+// battgen is installed
+const SHIPPED_CELLS: [(&str, &str); 8] = [
+    ("leaf_2012", include_str!("../examples/cells/leaf_2012.ron")),
+    ("lfp_100ah", include_str!("../examples/cells/lfp_100ah.ron")),
+    ("lfp_200ah", include_str!("../examples/cells/lfp_200ah.ron")),
+    ("lfp_202ah", include_str!("../examples/cells/lfp_202ah.ron")),
+    ("lfp_280ah", include_str!("../examples/cells/lfp_280ah.ron")),
+    ("lfp_75ah", include_str!("../examples/cells/lfp_75ah.ron")),
+    ("m50t_21700", include_str!("../examples/cells/m50t_21700.ron")),
+    ("tesla_21700", include_str!("../examples/cells/tesla_21700.ron")),
+];
+
+// a directory of the user's own cell files (any format), looked up before the shipped
+// ones; a file there with a shipped ID replaces it
+pub const CELL_LIBRARY_VAR: &str = "BATTGEN_CELLS";
+
+// library cell files in $BATTGEN_CELLS, by ID (file name without extension)
+fn user_library() -> Vec<(String, String)> {
+    let dir = match std::env::var_os(CELL_LIBRARY_VAR) {
+        Some(d) => d,
+        None => return Vec::new(),
+    };
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.to_str().and_then(Format::from_path).is_some())
+            .filter_map(|p| {
+                let id = p.file_stem()?.to_string_lossy().to_string();
+                Some((id, p.to_string_lossy().to_string()))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// every library ID: the user's and the shipped cells
+pub fn cell_library() -> Vec<String> {
+    let mut ids: Vec<String> = user_library().into_iter().map(|(id, _)| id).collect();
+    ids.extend(SHIPPED_CELLS.iter().map(|(id, _)| id.to_string()));
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+// a library ID or a cell file, relative to `base` (the design file's directory) unless
+// absolute. anything with a format's extension or a directory in it is a path; a bare
+// name is a file if one exists there, and a library ID otherwise.
+pub fn read_cell(cell: &str, base: &Path) -> Result<Module, Error> {
+    let local = base.join(cell);
+    if Format::from_path(cell).is_some()
+        || cell.contains(std::path::MAIN_SEPARATOR)
+        || local.is_file()
+    {
+        return read_module(&local.to_string_lossy());
+    }
+    if let Some((_, path)) = user_library().into_iter().find(|(id, _)| id == cell) {
+        return read_module(&path);
+    }
+    match SHIPPED_CELLS.iter().find(|(id, _)| *id == cell) {
+        Some((id, text)) => {
+            let name = format!("library cell {}", id);
+            let input: InputModule = parse_text_as(text, &name, Format::Ron)?;
+            Ok(input_module_to_module(input))
+        }
+        None => Err(Error::not_found(
+            cell,
+            format!("unknown cell ID (library: {})", cell_library().join(", ")),
+        )),
+    }
+}
+
+// the other way: a library cell file becomes its ID, anything else its full path.
+// a copy of a shipped cell file counts when its text is unchanged.
+pub fn cell_reference(filename: &str) -> String {
+    let path = match Path::new(filename).canonicalize() {
+        Ok(p) => p,
+        Err(_) => return filename.to_string(),
+    };
+    let id = path
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().to_string());
+    let in_user_library = user_library()
+        .iter()
+        .any(|(i, p)| *i == id && Path::new(p).canonicalize().ok().as_ref() == Some(&path));
+    let shipped = SHIPPED_CELLS.iter().any(|(i, text)| {
+        *i == id
+            && !user_library().iter().any(|(u, _)| *u == id)
+            && std::fs::read_to_string(&path).is_ok_and(|t| t == *text)
+    });
+    if in_user_library || shipped {
+        id
+    } else {
+        path.to_string_lossy().to_string()
+    }
+}
+
+impl InputPack {
+    // what -i and -t describe
+    pub fn from_cell(filename: &str, series: i32, parallel: i32) -> InputPack {
+        InputPack {
+            name: String::new(),
+            arrays: vec![InputModuleArray {
+                cell: cell_reference(filename),
+                series,
+                parallel,
+            }],
+            electrical: None,
+            mechanical: None,
+            thermal: None,
+        }
+    }
+}
+
+pub fn read_input_pack(filename: &str) -> Result<InputPack, Error> {
+    read_file(filename)
+}
+
+pub fn read_input_pack_as(filename: &str, format: Format) -> Result<InputPack, Error> {
+    read_file_as(filename, format)
+}
+
+// `base` is where relative cell paths start from: the design file's directory
+pub fn input_pack_to_battery(p: &InputPack, base: &Path) -> Result<Battery, Error> {
+    if p.arrays.is_empty() {
+        return Err(Error::Infeasible(
+            "pack design has no module arrays".to_string(),
+        ));
+    }
+    let mut b = Battery::new();
+    for a in &p.arrays {
+        let m = read_cell(&a.cell, base)?;
+        b.module_array.push(ModuleArray::new(m, a.series, a.parallel));
+    }
+    b.e_params = p.electrical;
+    b.m_params = p.mechanical;
+    b.t_params = match p.thermal.clone() {
+        Some(t) => Some(input_thermal_to_thermal(t)?),
+        None => None,
     };
+    Ok(b)
+}
+
+// call with io::read_pack("design.ron")?
+pub fn read_pack(filename: &str) -> Result<Battery, Error> {
+    let base = Path::new(filename).parent().unwrap_or_else(|| Path::new(""));
+    input_pack_to_battery(&read_input_pack(filename)?, base)
+}
+
+pub fn write_pack(pack: &InputPack, filename: &str) -> Result<(), Error> {
+    write_pack_as(pack, filename, Format::resolve(filename, None))
 }
 
-pub fn write_module(module: &InputModule, filename: &str) {
-    // let val = Value::from_str(module).expect("Failed to deserialize");
-    // let mut ser = serde_json::Serializer::pretty(std::)
+pub fn write_pack_as(pack: &InputPack, filename: &str, format: Format) -> Result<(), Error> {
+    write_file_as(pack, filename, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // read -> write -> read has to give back every value of every shipped cell,
+    // in every format
+    #[test]
+    fn cell_files_round_trip() {
+        let dir = std::env::temp_dir().join(format!("battgen_round_trip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let library = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/cells");
+        let cells = std::fs::read_dir(library).unwrap();
+        let mut checked = 0;
+        for entry in cells {
+            let path = entry.unwrap().path();
+            let original = read_module(path.to_str().unwrap()).unwrap();
+            for format in &[Format::Ron, Format::Json, Format::Toml, Format::Yaml] {
+                let stem = path.file_stem().unwrap().to_str().unwrap();
+                let copy = dir.join(format!("{}.{}", stem, format.get_name()));
+                let copy = copy.to_str().unwrap();
+                write_module(&module_to_input_module(&original), copy).unwrap();
+                assert_eq!(
+                    read_module(copy).unwrap(),
+                    original,
+                    "{} as {}",
+                    stem,
+                    format.get_name()
+                );
+            }
+            checked += 1;
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(checked > 0, "no cell files in examples/cells");
+    }
+
+    // a cell added to examples/cells has to be built in too, or its ID won't resolve
+    #[test]
+    fn shipped_cells_cover_examples() {
+        let library = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/cells");
+        for entry in std::fs::read_dir(library).unwrap() {
+            let path = entry.unwrap().path();
+            let id = path.file_stem().unwrap().to_str().unwrap();
+            let text = std::fs::read_to_string(&path).unwrap();
+            assert!(
+                SHIPPED_CELLS.iter().any(|(i, t)| *i == id && *t == text),
+                "{} is missing from SHIPPED_CELLS",
+                id
+            );
+            read_cell(id, Path::new("")).unwrap();
+        }
+    }
+
+    // a local file wins over the library cell of the same name
+    #[test]
+    fn local_cell_file_first() {
+        let dir = std::env::temp_dir().join(format!("battgen_local_cell_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tesla = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/cells/tesla_21700.ron");
+        std::fs::copy(tesla, dir.join("lfp_202ah")).unwrap();
+        let local = read_cell("lfp_202ah", &dir).unwrap();
+        assert!(matches!(local.shape, Shape::Cylinder));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let library = read_cell("lfp_202ah", &dir).unwrap();
+        assert!(!matches!(library.shape, Shape::Cylinder));
+    }
+
+    // rows come back sorted; an empty table or two rows at one temperature don't load
+    #[test]
+    fn coolant_tables_are_checked() {
+        let dir = std::env::temp_dir().join(format!("battgen_coolant_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let row = |t: f32| {
+            format!(
+                "(temp: {}, density: 800, specific_heat: 2000, viscosity: 0.01, conductivity: 0.1)",
+                t
+            )
+        };
+        let load = |name: &str, temps: &[f32]| {
+            let rows: Vec<String> = temps.iter().map(|&t| row(t)).collect();
+            let text = format!(
+                "Coolant(name: \"{}\", table: [{}], freezing_point: 200, boiling_point: 500, \
+                 flammable: false, conductive: false, molar_mass: 0.3)",
+                name,
+                rows.join(", ")
+            );
+            let path = dir.join(format!("{}.ron", name));
+            std::fs::write(&path, text).unwrap();
+            read_coolant(path.to_str().unwrap())
+        };
+        let c = load("unsorted", &[330.0, 270.0, 300.0]).unwrap();
+        let temps: Vec<f32> = c.table.iter().map(|r| r.temp).collect();
+        assert_eq!(temps, vec![270.0, 300.0, 330.0]);
+        assert!(matches!(load("empty", &[]), Err(Error::Invalid(_))));
+        assert!(matches!(load("twice", &[300.0, 270.0, 300.0]), Err(Error::Invalid(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
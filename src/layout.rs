@@ -0,0 +1,317 @@
+/*
+    placement layer: where every cell/module physically sits in the pack.
+    anything spatial (mass properties, drawings, CAD) should be computed from this,
+    not re-derived from topology.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// one module (or lone cell) placed in the pack frame.
+// pack frame: origin at the minimum corner of the cell block, z up, cells stand on z = 0.
+#[derive(Copy, Clone, Debug)]
+pub struct CellPlacement {
+    pub module: Module,
+    // center of the placed bounding box, meters
+    pub pos: [f32; 3],
+    // placed bounding box (x, y, z), meters
+    pub size: [f32; 3],
+    // which ModuleArray of the Battery this came from
+    pub array: usize,
+    // series group across the whole pack, and index within that parallel group
+    pub group: usize,
+    pub member: usize,
+    // axial cells: standing negative end up. others: turned 180 degrees about z.
+    pub flip: bool,
+}
+
+impl CellPlacement {
+    // (positive, negative) terminal positions in xy. axial terminals are in the middle,
+    // one on each face; end terminals sit a quarter width in from each edge of the top.
+    pub fn get_terminals(&self) -> ([f32; 2], [f32; 2]) {
+        let p = [self.pos[0], self.pos[1]];
+        if self.module.is_axial() {
+            return (p, p);
+        }
+        let side = if self.flip { -0.25 } else { 0.25 };
+        (
+            [p[0], p[1] + side * self.size[1]],
+            [p[0], p[1] - side * self.size[1]],
+        )
+    }
+}
+
+// anything with mass that isn't a cell: busbars, brackets, enclosure, BMS boards...
+#[derive(Clone, Debug)]
+pub struct Component {
+    pub name: String,
+    pub mass: f32,
+    // center of a solid box standing in for the part, meters
+    pub pos: [f32; 3],
+    pub size: [f32; 3],
+}
+
+// copper connecting one series group to the next (see busbar.rs). stored as a union of
+// flat axis-aligned rectangles (xy, meters) lying on the top or bottom face of the cells.
+#[derive(Clone, Debug)]
+pub struct Busbar {
+    pub from_group: usize,
+    pub to_group: usize,
+    pub pieces: Vec<([f32; 2], [f32; 2])>,
+    // z of the bar's lower face and its thickness, meters
+    pub z: f32,
+    pub thickness: f32,
+    pub top: bool,
+    // routed strip length (m) and effective resistance of the junction (ohm)
+    pub length: f32,
+    pub resistance: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    pub cells: Vec<CellPlacement>,
+    pub components: Vec<Component>,
+    pub busbars: Vec<Busbar>,
+}
+
+pub const COPPER_DENSITY: f32 = 8960.0;
+// design current density for busbars, A/m2 (4 A/mm2, conservative for copper in air)
+pub const BUSBAR_CURRENT_DENSITY: f32 = 4e6;
+pub const BUSBAR_MIN_THICKNESS: f32 = 0.2e-3;
+
+impl Busbar {
+    // footprint of the copper: pieces overlap where strips meet and under the terminal
+    // pads, so this is the area of their union, one x slab at a time
+    pub fn get_area(&self) -> f32 {
+        let mut xs: Vec<f32> = self
+            .pieces
+            .iter()
+            .flat_map(|(lo, hi)| vec![lo[0], hi[0]])
+            .collect();
+        xs.sort_by(f32::total_cmp);
+        xs.dedup();
+        let mut area: f32 = 0.0;
+        for x in xs.windows(2) {
+            let mid = 0.5 * (x[0] + x[1]);
+            let mut spans: Vec<(f32, f32)> = self
+                .pieces
+                .iter()
+                .filter(|(lo, hi)| lo[0] <= mid && mid <= hi[0])
+                .map(|(lo, hi)| (lo[1], hi[1]))
+                .collect();
+            spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut covered: f32 = 0.0;
+            let mut reach = f32::MIN;
+            for (lo, hi) in spans {
+                if hi > reach {
+                    covered += hi - lo.max(reach);
+                    reach = hi;
+                }
+            }
+            area += covered * (x[1] - x[0]);
+        }
+        area
+    }
+
+    // summed area of the pieces, overlaps counted twice
+    pub fn get_piece_area(&self) -> f32 {
+        self.pieces
+            .iter()
+            .map(|(lo, hi)| (hi[0] - lo[0]) * (hi[1] - lo[1]))
+            .sum()
+    }
+
+    pub fn get_mass(&self) -> f32 {
+        self.get_area() * self.thickness * COPPER_DENSITY
+    }
+}
+
+impl Module {
+    // bounding box of the module as it stands in the pack (x, y, z).
+    // prisms and pouches stack along their thinnest face (dims[2]) and stand on their
+    // long edge; custom shapes are placed the same way by bounding box.
+    // cylinders stand upright.
+    pub fn get_placed_size(&self) -> [f32; 3] {
+        match self.shape {
+            Shape::Cylinder => [self.dims[0], self.dims[0], self.dims[1]],
+            _ => [self.dims[2], self.dims[1], self.dims[0]],
+        }
+    }
+}
+
+impl Layout {
+    pub fn new() -> Layout {
+        Layout {
+            cells: Vec::new(),
+            components: Vec::new(),
+            busbars: Vec::new(),
+        }
+    }
+
+    // naive starting layout: each series group is a column of parallel cells along y,
+    // series groups follow each other along x and fold back (serpentine) into
+    // enough rows to keep the block roughly square. hex-arranged cylinder columns are
+    // staggered by half a pitch so they nest.
+    pub fn new_from(b: &Battery) -> Layout {
+        let mut layout = Layout::new();
+        let mut group: usize = 0;
+        let mut y0: f32 = 0.0;
+        for (i, ma) in b.module_array.iter().enumerate() {
+            let size = ma.module.get_placed_size();
+            let pitch = ma.module.get_pitch();
+            let stagger = match (ma.module.shape, ma.module.get_arrangement()) {
+                (Shape::Cylinder, Arrangement::Hex) => 0.5 * pitch[1],
+                _ => 0.0,
+            };
+            let (s, p) = (ma.series.max(1) as usize, ma.parallel.max(1) as usize);
+            let row_len = s as f32 * pitch[0];
+            // rows carry on the columns' lattice, so staggered columns nest across them
+            // too; the stagger only widens the block once
+            let band = p as f32 * pitch[1];
+            let row_wid = band + stagger;
+            let rows = ((row_len / row_wid).sqrt().round() as usize).max(1).min(s);
+            let per_row = s.div_ceil(rows);
+            for k in 0..s {
+                let (row, mut col) = (k / per_row, k % per_row);
+                if row % 2 == 1 {
+                    col = per_row - 1 - col;
+                }
+                let offset = if col % 2 == 1 { stagger } else { 0.0 };
+                for j in 0..p {
+                    layout.cells.push(CellPlacement {
+                        module: ma.module,
+                        pos: [
+                            0.5 * size[0] + col as f32 * pitch[0],
+                            y0 + row as f32 * band + offset + (j as f32 + 0.5) * pitch[1],
+                            0.5 * size[2],
+                        ],
+                        size,
+                        array: i,
+                        group: group + k,
+                        member: j,
+                        flip: (group + k) % 2 == 1,
+                    });
+                }
+            }
+            group += s;
+            y0 += s.div_ceil(per_row) as f32 * band + stagger;
+        }
+        layout
+    }
+
+    // sum of the packed volumes of every placed module, m3: an estimate per module,
+    // not the space this placement takes up (see get_bounding_volume)
+    pub fn get_packed_volume(&self) -> f32 {
+        self.cells
+            .iter()
+            .map(|c| c.module.get_min_volume_packed())
+            .sum()
+    }
+
+    // box around everything placed, busbars and holders included, m3
+    pub fn get_bounding_volume(&self) -> f32 {
+        let (lo, hi) = self.get_bounds();
+        (hi[0] - lo[0]) * (hi[1] - lo[1]) * (hi[2] - lo[2])
+    }
+
+    pub fn get_group(&self, group: usize) -> Vec<&CellPlacement> {
+        self.cells.iter().filter(|c| c.group == group).collect()
+    }
+
+    pub fn get_group_count(&self) -> usize {
+        self.cells.iter().map(|c| c.group + 1).max().unwrap_or(0)
+    }
+
+    pub fn add_component(&mut self, c: Component) {
+        self.components.push(c);
+    }
+
+    // axis-aligned extents of everything placed: (min corner, max corner)
+    pub fn get_bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut lo = [f32::MAX; 3];
+        let mut hi = [f32::MIN; 3];
+        let boxes = self
+            .cells
+            .iter()
+            .map(|c| (c.pos, c.size))
+            .chain(self.components.iter().map(|c| (c.pos, c.size)))
+            .chain(self.busbars.iter().flat_map(|b| {
+                b.pieces.iter().map(move |(lo, hi)| {
+                    (
+                        [0.5 * (lo[0] + hi[0]), 0.5 * (lo[1] + hi[1]), b.z + 0.5 * b.thickness],
+                        [hi[0] - lo[0], hi[1] - lo[1], b.thickness],
+                    )
+                })
+            }));
+        for (pos, size) in boxes {
+            for a in 0..3 {
+                lo[a] = lo[a].min(pos[a] - 0.5 * size[a]);
+                hi[a] = hi[a].max(pos[a] + 0.5 * size[a]);
+            }
+        }
+        if lo[0] > hi[0] {
+            return ([0.0; 3], [0.0; 3]);
+        }
+        (lo, hi)
+    }
+}
+
+impl Battery {
+    pub fn get_layout(&self) -> Layout {
+        let mut layout = Layout::new_from(self);
+        layout.assign_groups(self);
+        layout.route_busbars();
+        layout.add_holders();
+        layout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cells in one column of a two-row block sit one pitch apart, across the fold too
+    fn column_spacing(arrangement: Arrangement) -> (f32, Vec<f32>) {
+        let mut cell = read_cell("tesla_21700", std::path::Path::new("")).unwrap();
+        let mut h = cell.holder.unwrap();
+        h.arrangement = arrangement;
+        cell.holder = Some(h);
+        let layout = Battery::new_from(cell, 24, 4).get_layout();
+        let x = layout.cells[0].pos[0];
+        let mut ys: Vec<f32> = layout
+            .cells
+            .iter()
+            .filter(|c| (c.pos[0] - x).abs() < 1e-6)
+            .map(|c| c.pos[1])
+            .collect();
+        ys.sort_by(|a, b| a.total_cmp(b));
+        let steps = ys.windows(2).map(|w| w[1] - w[0]).collect();
+        (cell.get_pitch()[1], steps)
+    }
+
+    #[test]
+    fn rows_nest_for_hex_and_square() {
+        for &arrangement in [Arrangement::Hex, Arrangement::Square].iter() {
+            let (pitch, steps) = column_spacing(arrangement);
+            // 4 cells a row, 2 rows
+            assert_eq!(steps.len(), 7);
+            for step in steps {
+                assert!((step - pitch).abs() < 1e-5, "{:?}: {} vs {}", arrangement, step, pitch);
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+/*
+    battgen library: cells, packs, the analyses on them and the file formats they're
+    stored in. the battgen CLI (main.rs) is a thin layer over this; so is anything
+    else that wants to build or check a pack. print-outs live in the CLI's report.
+    MKS except for Ah
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+extern crate ron;
+
+pub mod electrical;
+pub use electrical::*;
+
+pub mod mechanical;
+pub use mechanical::*;
+
+pub mod thermal;
+pub use thermal::*;
+
+pub mod coolant;
+pub use coolant::*;
+
+pub mod coldplate;
+pub use coldplate::*;
+
+pub mod aircool;
+pub use aircool::*;
+
+pub mod runaway;
+pub use runaway::*;
+
+pub mod preheat;
+pub use preheat::*;
+
+pub mod temperature;
+pub use temperature::*;
+
+pub mod gradient;
+pub use gradient::*;
+
+pub mod validate;
+pub use validate::*;
+
+pub mod error;
+pub use error::*;
+
+pub mod io;
+pub use io::*;
+
+pub mod layout;
+pub use layout::*;
+
+pub mod fixture;
+pub use fixture::*;
+
+pub mod cad;
+pub use cad::*;
+
+pub mod svg;
+
+pub mod holder;
+pub use holder::*;
+
+pub mod busbar;
+pub use busbar::*;
+
+pub mod datasheet;
+pub use datasheet::*;
+
+const PI: f32 = std::f32::consts::PI;
@@ -1,6 +1,7 @@
 /*
     battgen: EV-focused battery generator tooling
     proof of concept; don't use this in production.
+    command line front end; the work is done in the library (lib.rs).
     MKS except for Ah
 
     Copyright (C) 2020 Paul Hansel
@@ -22,34 +23,15 @@
 extern crate clap;
 use clap::{App, Arg, SubCommand};
 
-extern crate serde;
-use serde::{Deserialize, Serialize};
-
-extern crate ron;
-use ron::value::Value;
-
 use colored::*;
 
-use std::env;
-use std::{collections::HashMap, fs::File};
-
-// local imports
-mod electrical;
-use electrical::*;
-
-mod mechanical;
-use mechanical::*;
-
-mod thermal;
-use thermal::*;
-
-mod io;
-use io::*;
+use battgen::*;
 
+// cli-only
 mod demo;
 use demo::*;
-
-const PI: f32 = (3.1415926 as f32);
+mod report;
+use report::*;
 
 /*
 What does bg do? It takes these things:
@@ -72,7 +54,28 @@ bg --input-cell m50_21700.json --topology 4S4P --balance-stability
 > prints expected lifetime given battery output/input requirements
 */
 
+// the one place errors turn into exit codes
 fn main() {
+    if let Err(e) = run() {
+        let code = match &e {
+            Error::Io { .. } => 2,
+            Error::Parse { .. } => 3,
+            Error::Validation(findings) => {
+                for (i, f) in findings {
+                    print_finding(f, *i);
+                }
+                println!("Fix the cell file errors above before analyzing this pack.");
+                4
+            }
+            Error::Infeasible(_) => 5,
+            Error::Invalid(_) => 6,
+        };
+        println!("{} {}", "Error:".red(), e);
+        std::process::exit(code);
+    }
+}
+
+fn run() -> Result<(), Error> {
     let matches = App::new("battgen")
         .version("0.1")
         .author("Paul Hansel <paul.hansel@colorado.edu>")
@@ -94,7 +97,14 @@ fn main() {
                 .short("i")
                 .long("input-cell")
                 .value_name("/path/to/input_file.ron")
-                .help("Takes an input cell file")
+                .help("Takes an input cell file, or a library cell ID (shipped cells plus any in $BATTGEN_CELLS)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("save_cell")
+                .long("save-cell")
+                .value_name("/path/to/output_file.ron")
+                .help("Writes the cell (after any holder options) back out as a normalized cell file")
                 .takes_value(true),
         )
         .arg(
@@ -111,111 +121,654 @@ fn main() {
                 .long("demo")
                 .help("Print demonstration pack analysis"),
         )
+        .arg(
+            Arg::with_name("mass")
+                .short("m")
+                .long("mass-properties")
+                .help("Prints pack mass, center of gravity and inertia tensor"),
+        )
+        .arg(
+            Arg::with_name("vehicle_origin")
+                .long("vehicle-origin")
+                .value_name("X,Y,Z")
+                .help("Position of the pack frame origin in the vehicle frame (m)")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .validator(|v| parse_triple(&v).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("vehicle_rpy")
+                .long("vehicle-rpy")
+                .value_name("ROLL,PITCH,YAW")
+                .help("Orientation of the pack frame in the vehicle frame (degrees)")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .validator(|v| parse_triple(&v).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name("mech")
+                .long("mech")
+                .value_name("/path/to/mechanical.ron")
+                .help("Takes mount and cell retention capacities and checks inertial loads")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("load_case")
+                .long("load-case")
+                .value_name("NAME")
+                .help("Checks a preset load case instead of peak_accel: r100-longitudinal, r100-lateral, vertical-shock, vibration, all")
+                .takes_value(true)
+                .multiple(true)
+                .requires("mech"),
+        )
+        .arg(
+            Arg::with_name("fixture")
+                .long("fixture")
+                .help("Sizes end plates and straps for prismatic and pouch cell stacks"),
+        )
+        .arg(
+            Arg::with_name("scad")
+                .long("scad")
+                .value_name("/path/to/output.scad")
+                .help("Exports the packed layout as an OpenSCAD script")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stl")
+                .long("stl")
+                .value_name("/path/to/output.stl")
+                .help("Exports the packed layout as ASCII STL, one solid per body")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("svg")
+                .long("svg")
+                .value_name("/path/to/output.svg")
+                .help("Draws a top view of the packed layout with polarity and busbars")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("busbars")
+                .long("busbars")
+                .help("Routes series busbars and prints copper mass and junction resistances"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("/path/to/profile.ron")
+                .help("Simulates module temperature and SoC over a pack current profile")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .value_name("/path/to/trace.csv")
+                .help("Writes the simulated temperature and SoC trace as CSV")
+                .takes_value(true)
+                .requires("profile"),
+        )
+        .arg(
+            Arg::with_name("coolant")
+                .long("coolant")
+                .value_name("NAME|/path/to/coolant.ron")
+                .help("Prints coolant properties; NAME from --coolants or water-eg-NN / water-pg-NN")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("limits")
+                .long("limits")
+                .help("Checks the simulated profile against the charge, discharge and storage temperature limits")
+                .requires("profile"),
+        )
+        .arg(
+            Arg::with_name("temp_profile")
+                .long("temp-profile")
+                .value_name("/path/to/temperatures.ron")
+                .help("Checks specified or logged module temperatures against the operating limits")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("thermal")
+                .long("thermal")
+                .value_name("/path/to/thermal.ron")
+                .help("Takes coolant and design load, and sizes a cold plate under the cells")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("gradient")
+                .long("gradient")
+                .value_name("/path/to/gradient.ron")
+                .help("Takes plate face and interface materials, and solves the steady cell-to-cell temperature spread")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("air")
+                .long("air")
+                .value_name("/path/to/air.ron")
+                .help("Takes gap, ambient and fan curve, and analyzes natural and forced air cooling")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("preheat")
+                .long("preheat")
+                .value_name("/path/to/preheat.ron")
+                .help("Takes ambient, insulation and heater power, and estimates the time and energy to reach charging temperature")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("coolants")
+                .long("coolants")
+                .help("Lists the built-in coolant library"),
+        )
+        .arg(
+            Arg::with_name("heat")
+                .long("heat")
+                .value_name("AMPS")
+                .help("Prints pack I2R and entropic heat against SoC at a pack current, 25 C")
+                .takes_value(true)
+                .validator(is_number),
+        )
+        .arg(
+            Arg::with_name("runaway")
+                .long("runaway")
+                .help("Starts thermal runaway in one cell and checks propagation and barriers"),
+        )
+        .arg(
+            Arg::with_name("runaway_cell")
+                .long("runaway-cell")
+                .value_name("INDEX")
+                .help("Cell to start runaway in (default: the most surrounded one)")
+                .takes_value(true)
+                .validator(is_index)
+                .requires("runaway"),
+        )
+        .arg(
+            Arg::with_name("holder")
+                .long("holder")
+                .help("Packs cylindrical cells in holders and prints holder size and mass"),
+        )
+        .arg(
+            Arg::with_name("arrangement")
+                .long("arrangement")
+                .value_name("hex|square")
+                .help("Cylindrical cell arrangement in the holder")
+                .takes_value(true)
+                .possible_values(&["hex", "square"]),
+        )
+        .arg(
+            Arg::with_name("holder_gap")
+                .long("holder-gap")
+                .value_name("MM")
+                .help("Gap between neighbouring cylindrical cells in the holder (mm)")
+                .takes_value(true)
+                .validator(is_length),
+        )
+        .arg(
+            Arg::with_name("pack")
+                .long("pack")
+                .value_name("/path/to/design.ron")
+                .help("Takes a pack design file instead of -i and -t")
+                .takes_value(true)
+                .conflicts_with_all(&["input", "topology"]),
+        )
+        .arg(
+            Arg::with_name("save_pack")
+                .long("save-pack")
+                .value_name("/path/to/design.ron")
+                .help("Writes the pack (cells, topology, --mech and --thermal files) as a pack design file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("ron|json|toml|yaml")
+                .possible_values(&["ron", "json", "toml", "yaml"])
+                .help("File format for --pack, --save-cell and --save-pack files whose extension doesn't name one, and for imported cell files (default ron)")
+                .takes_value(true)
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("pack")
+                .about("Works from a pack design file")
+                .subcommand(
+                    SubCommand::with_name("analyze")
+                        .about("Prints the pack overview and every analysis the design file has parameters for")
+                        .arg(
+                            Arg::with_name("design")
+                                .value_name("/path/to/design.ron")
+                                .required(true)
+                                .index(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Reads cells from a vendor datasheet (a table pasted as text, or label/value lines)")
+                .arg(
+                    Arg::with_name("sheet")
+                        .value_name("/path/to/datasheet.txt")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("out_dir")
+                        .long("out-dir")
+                        .value_name("/path/to/cells")
+                        .help("Writes a cell file for each cell found into the directory, named after the datasheet file and the cell")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Overwrites cell files that already exist in --out-dir"),
+                ),
+        )
         .get_matches();
 
-    let default_cell = format!(
-        "{}/examples/cells/m50t_21700.ron",
-        env!("CARGO_MANIFEST_DIR")
-    );
+    // file extensions pick the format; --format covers files without one
+    let format = matches.value_of("format").and_then(Format::from_name);
+
+    // import stands alone: cell files come out, no pack goes in
+    if let Some(import) = matches.subcommand_matches("import") {
+        let sheet = import.value_of("sheet").unwrap_or_default();
+        println!("Importing datasheet: {}", sheet);
+        let cells = read_datasheet(sheet)?;
+        let format = format.unwrap_or(Format::Ron);
+        // the sheet's name keeps generated names (lfp_100ah) clear of the shipped cells
+        let prefix = std::path::Path::new(sheet)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_lowercase().replace(' ', "_"))
+            .unwrap_or_default();
+        for cell in &cells {
+            print_imported_cell(cell);
+            if let Some(dir) = import.value_of("out_dir") {
+                let fname = format!("{}/{}_{}.{}", dir, prefix, cell.name, format.get_name());
+                let force = import.is_present("force");
+                if !force && std::path::Path::new(&fname).exists() {
+                    println!("{} {} exists; --force replaces it", "Skipped:".yellow(), fname);
+                    continue;
+                }
+                write_imported_cell(cell, &fname, format, force)?;
+                println!("Saved cell file: {}", fname);
+            }
+        }
+        println!("Done.");
+        return Ok(());
+    }
 
-    // check if input cell file provided; if not, use m50_21700 model's filename
-    let input_file = matches.value_of("input").unwrap_or(&default_cell);
+    // a pack design file replaces -i and -t
+    let pack_file = matches.value_of("pack").or_else(|| {
+        matches
+            .subcommand_matches("pack")
+            .and_then(|p| p.subcommand_matches("analyze"))
+            .and_then(|a| a.value_of("design"))
+    });
+
+    let (mut design, base, demo_args) = match pack_file {
+        Some(fname) => {
+            println!("Using pack design: {}", fname);
+            let base = std::path::Path::new(fname)
+                .parent()
+                .unwrap_or_else(|| std::path::Path::new(""))
+                .to_path_buf();
+            (
+                read_input_pack_as(fname, Format::resolve(fname, format))?,
+                base,
+                None,
+            )
+        }
+        None => {
+            // check if input cell file provided; if not, use the m50t_21700 library cell
+            let input_file = matches.value_of("input").unwrap_or("m50t_21700");
+            println!("Using input file: {}", input_file);
+
+            // do they want to generate a new pack? if not, it's still 1S1P (i.e. a lone cell)
+            let default_topo = "1S.1P";
+            let topology = matches.value_of("topology").unwrap_or(default_topo);
+            println!("Topology provided: {}", topology); 
+            let topo_arr: Vec<&str> = topology.split("S").collect();
+            let topo_s: &str = topo_arr[0];
+            let topo_p: Vec<&str> = topo_arr[1].split("P").collect();
+
+            let topo_sn: i32 = topo_s.parse().unwrap_or(1);
+            let topo_pn: i32 = topo_p[0].parse().unwrap_or(1);
+
+            println!("Topology parsed as: {}S {}P", topo_sn, topo_pn);
+
+            (
+                InputPack::from_cell(input_file, topo_sn, topo_pn),
+                std::path::PathBuf::new(),
+                Some((input_file.to_string(), topo_sn, topo_pn)),
+            )
+        }
+    };
 
-    // read file into battgen-internal data structures
-    let m = read_module(&input_file);
+    // parameter files given on the command line override the design's own
+    if let Some(mech_file) = matches.value_of("mech") {
+        design.mechanical = Some(read_mechanical_params(mech_file)?);
+    }
+    if let Some(fname) = matches.value_of("thermal") {
+        design.thermal = Some(read_input_thermal_params(fname)?);
+    }
 
-    println!("Using input file: {}", input_file);
+    // read files into battgen-internal data structures
+    let mut b = input_pack_to_battery(&design, &base)?;
+
+    // holder options override (or create) the cell file's holder
+    let holder_opts = ["holder", "arrangement", "holder_gap"];
+    if holder_opts.iter().any(|o| matches.is_present(o)) {
+        for ma in b.module_array.iter_mut() {
+            if let Shape::Cylinder = ma.module.shape {
+                let mut h = ma.module.holder.unwrap_or_default();
+                match matches.value_of("arrangement") {
+                    Some("square") => h.arrangement = Arrangement::Square,
+                    Some("hex") => h.arrangement = Arrangement::Hex,
+                    _ => {}
+                }
+                if let Some(gap) = matches.value_of("holder_gap") {
+                    // checked by is_length
+                    h.gap = gap.parse::<f32>().unwrap() / 1000.0;
+                }
+                ma.module.holder = Some(h);
+            } else {
+                println!("Cell holders are for cylindrical cells only; ignoring holder options.");
+            }
+        }
+    }
+    let m = b.module_array[0].module;
 
-    // do they want to generate a new pack? if not, it's still 1S1P (i.e. a lone cell)
-    let default_topo = "1S.1P";
-    let mut topology = matches.value_of("topology").unwrap_or(default_topo);
-    println!("Topology provided: {}", topology); 
-    let mut topo_arr: Vec<&str> = topology.split("S").collect();
-    let topo_s: &str = topo_arr[0];
-    let topo_p: Vec<&str> = topo_arr[1].split("P").collect();
+    // catch typos and unit mix-ups before they turn into confident-looking numbers
+    for (i, f) in b.validate()? {
+        print_finding(&f, i);
+    }
+
+    if let Some((input_file, topo_sn, topo_pn)) = demo_args {
+        demo_from_filename(&input_file, topo_sn, topo_pn)?;
+    }
 
-    let topo_sn: i32 = topo_s.parse().unwrap_or(1);
-    let topo_pn: i32 = topo_p[0].parse().unwrap_or(1);
+    if let Some(out) = matches.value_of("save_cell") {
+        write_module_as(
+            &module_to_input_module(&m),
+            out,
+            Format::resolve(out, format),
+        )?;
+        println!("Wrote cell file to {}", out);
+    }
 
-    println!("Topology parsed as: {}S {}P", topo_sn, topo_pn);
+    if let Some(out) = matches.value_of("save_pack") {
+        write_pack_as(&design, out, Format::resolve(out, format))?;
+        println!("Wrote pack design to {}", out);
+    }
 
     // actually print out what the use asked for.
-    match matches.occurrences_of("v") {
-        0 => m.print_electrical_nominal(),
-        1 => {
-            m.print_electrical_nominal();
-            m.print_overview_ev();
-        }
-        2 => {
-            m.print_overview();
-            m.print_overview_ev();
-            m.print_mechanical();
-            m.print_electrical_nominal();
-        }
-        // you're the one who typed -vvv.
-        3 | _ => {
-            m.print_overview();
-            m.print_overview_ev();
-            m.print_mechanical();
-            m.print_electrical_nominal();
-            m.print_overview();
-            m.print_overview_ev();
-            m.print_mechanical();
-            m.print_electrical_nominal();
+    for ma in &b.module_array {
+        let m = ma.module;
+        match matches.occurrences_of("v") {
+            0 => print_module_electrical(&m),
+            1 => {
+                print_module_electrical(&m);
+                print_module_overview(&m);
+            }
+            2 => {
+                print_module_overview(&m);
+                print_module_mechanical(&m);
+                print_module_electrical(&m);
+            }
+            // you're the one who typed -vvv.
+            _ => {
+                print_module_overview(&m);
+                print_module_mechanical(&m);
+                print_module_electrical(&m);
+                print_module_overview(&m);
+                print_module_mechanical(&m);
+                print_module_electrical(&m);
+            }
+        };
+    }
+
+    if pack_file.is_some() {
+        print_pack_overview(&b);
+    }
+
+    if let Some(params) = b.m_params {
+        let layout = b.get_layout();
+        let mut cases: Vec<(String, MechanicalParams)> = Vec::new();
+        for name in matches.values_of("load_case").into_iter().flatten() {
+            if name == "all" {
+                for c in LoadCase::all().iter() {
+                    cases.push((c.get_name().to_string(), params.with_load_case(*c)));
+                }
+                continue;
+            }
+            match LoadCase::from_name(name) {
+                Some(c) => cases.push((c.get_name().to_string(), params.with_load_case(c))),
+                None => println!("Unknown load case: {}", name),
+            }
         }
-    };
+        if cases.is_empty() {
+            cases.push(("peak_accel".to_string(), params));
+        }
+        for (name, p) in cases {
+            println!("{} {}", "Load case:".cyan(), name);
+            for check in p.check_loads(&layout) {
+                print_load_check(&check);
+            }
+        }
+    }
 
-    demo_from_filename(input_file, topo_sn, topo_pn);
+    if matches.is_present("coolants") {
+        for c in Coolant::library() {
+            println!(
+                "  {:<18} freezes {:>6.1} K, boils {:>6.1} K",
+                c.name, c.freezing_point, c.boiling_point
+            );
+        }
+    }
 
-    // print out the Leaf demo if user asked for it.
-    match matches.occurrences_of("demo") {
-        0 => {}
-        1 | _ => {
-            demo();
+    if let Some(name) = matches.value_of("coolant") {
+        let coolant = if Format::from_path(name).is_some() {
+            Some(read_coolant(name)?)
+        } else {
+            Coolant::from_name(name)
+        };
+        match coolant {
+            Some(c) => print_coolant(&c),
+            None => println!("Unknown coolant: {} (see --coolants)", name),
         }
-    };
+    }
 
-    println!("Done.");
-}
+    if let Some(current) = matches.value_of("heat") {
+        // checked by is_number
+        print_heat(&b, current.parse().unwrap(), 298.15);
+    }
 
-// user-facing print-out functions in the cmdline
-impl Module {
-    pub fn print_overview_ev(self) {
-        self.print_mechanical();
-        self.print_electrical_nominal();
-        self.print_mass();
-        self.print_topology();
+    if b.t_params.is_some() {
+        print_cold_plate(&b.design_cold_plate()?);
     }
 
-    pub fn print_overview(self) {
-        self.print_mechanical();
-        self.print_electrical_nominal();
-        self.print_mass();
-        self.print_topology();
+    if let Some(fname) = matches.value_of("gradient") {
+        print_gradient(&b.analyze_gradient(&read_gradient_params(fname)?)?);
     }
 
-    pub fn print_mechanical(self) {
-        println!("Volume of cells in module: {} m3", self.get_volume());
-        println!(
-            "Volume with packing efficiency: {} m3",
-            self.get_min_volume_packed()
-        );
+    if let Some(fname) = matches.value_of("air") {
+        let ap = read_air_cooling_params(fname)?;
+        print_air_cooling(&b.analyze_air_cooling(&ap)?, ap.cell_temp_max);
+    }
+
+    if let Some(fname) = matches.value_of("preheat") {
+        print_preheat(&b.analyze_preheat(&read_preheat_params(fname)?)?);
+    }
+
+    if matches.is_present("runaway") {
+        let layout = b.get_layout();
+        let trigger = match matches.value_of("runaway_cell") {
+            Some(i) => i.parse().map_err(|_| {
+                Error::Invalid(format!("--runaway-cell: '{}' is not a cell index", i))
+            })?,
+            None => layout.get_runaway_trigger(),
+        };
+        let materials = gap_materials();
+        let base = layout.simulate_runaway(trigger, &materials[0], 298.15)?;
+        print_propagation(&base);
+        println!("Barriers between cells:");
+        for gap in materials.iter().skip(1) {
+            print_barrier(&layout.simulate_runaway(trigger, gap, 298.15)?);
+        }
     }
 
-    pub fn print_mass(self) {
-        println!("Module cell mass: {} kg", self.get_mass_kg());
+    if matches.is_present("holder") {
+        let holders = b.get_layout().get_holders();
+        if holders.is_empty() {
+            println!("No cell holders in this pack.");
+        }
+        for h in holders {
+            print_holder(&h);
+        }
     }
 
-    pub fn print_topology(self) {
-        println!("Module topology: {}S{}P", self.series, self.parallel);
+    if matches.is_present("fixture") {
+        let fp = b
+            .m_params
+            .and_then(|p| p.fixture)
+            .unwrap_or_default();
+        let n = b.get_layout().get_max_stack();
+        print_fixture(&m.design_fixture(n, &fp)?);
     }
 
-    pub fn print_electrical_nominal(self) {
+    if matches.is_present("scad") || matches.is_present("stl") {
+        let bodies = b.get_layout().get_bodies(Some(Enclosure::default()));
+        let outputs = [
+            (matches.value_of("scad"), to_openscad as fn(&[Body]) -> String),
+            (matches.value_of("stl"), to_stl),
+        ];
+        for (fname, writer) in outputs.iter() {
+            if let Some(fname) = fname {
+                match std::fs::write(fname, writer(&bodies)) {
+                    Ok(_) => println!("Wrote {} bodies to {}", bodies.len(), fname),
+                    Err(e) => println!("Failed to write {}: {}", fname, e),
+                }
+            }
+        }
+    }
+
+    if let Some(fname) = matches.value_of("svg") {
+        match std::fs::write(fname, b.get_layout().to_svg()) {
+            Ok(_) => println!("Wrote layout drawing to {}", fname),
+            Err(e) => println!("Failed to write {}: {}", fname, e),
+        }
+    }
+
+    if matches.is_present("busbars") {
+        let layout = b.get_layout();
+        print_routing(&layout.get_routing());
+        for bar in &layout.busbars {
+            println!(
+                "  S{} -> S{}: {:.1} mm copper, {:.2} mm thick, {:.1} uOhm",
+                bar.from_group + 1,
+                bar.to_group + 1,
+                bar.length * 1000.0,
+                bar.thickness * 1000.0,
+                bar.resistance * 1e6
+            );
+        }
         println!(
-            "Module nominal characteristics: {}V, {}Ah, {}kWh",
-            self.get_voltage(),
-            self.get_ah(),
-            self.get_kwh_nominal()
+            "Pack DC resistance at 50% SoC incl. busbars: {:.2} mOhm",
+            b.get_ir_dc_total(&0.5) * 1000.0
         );
     }
+
+    if let Some(fname) = matches.value_of("profile") {
+        let profile = read_profile(fname)?;
+        let traces = b.simulate(&profile);
+        for t in &traces {
+            print_trace(t);
+        }
+        if let Some(out) = matches.value_of("trace") {
+            let csv: String = traces.iter().map(|t| t.to_csv()).collect::<Vec<_>>().join("\n");
+            match std::fs::write(out, csv) {
+                Ok(_) => println!("Wrote temperature trace to {}", out),
+                Err(e) => println!("Failed to write {}: {}", out, e),
+            }
+        }
+        if matches.is_present("limits") {
+            print_limits(&b, &traces);
+        }
+    }
+
+    if let Some(fname) = matches.value_of("temp_profile") {
+        let traces = b.trace_temperatures(&read_temperature_profile(fname)?);
+        print_limits(&b, &traces);
+    }
+
+    if matches.is_present("mass") {
+        let mp = b.get_mass_properties();
+        println!("Mass properties in pack frame:");
+        print_mass_properties(&mp);
+        if matches.is_present("vehicle_origin") || matches.is_present("vehicle_rpy") {
+            // both checked by the validators
+            let rpy = parse_triple(matches.value_of("vehicle_rpy").unwrap_or("0,0,0")).unwrap();
+            let frame = VehicleFrame {
+                origin: parse_triple(matches.value_of("vehicle_origin").unwrap_or("0,0,0"))
+                    .unwrap(),
+                rpy: [rpy[0].to_radians(), rpy[1].to_radians(), rpy[2].to_radians()],
+            };
+            let vp = mp.in_vehicle_frame(&frame);
+            println!("Mass properties in vehicle frame:");
+            print_mass_properties(&vp);
+            println!("Inertia tensor about vehicle origin (kg-m2):");
+            for row in vp.get_inertia_about([0.0; 3]).iter() {
+                println!("  [{:>12.5} {:>12.5} {:>12.5}]", row[0], row[1], row[2]);
+            }
+        }
+    }
+
+    // print out the Leaf demo if user asked for it.
+    match matches.occurrences_of("demo") {
+        0 => {}
+        _ => {
+            demo()?;
+        }
+    };
+
+    println!("Done.");
+    Ok(())
+}
+
+// parses "x,y,z" into three floats; anything but three numbers is an error.
+fn parse_triple(s: &str) -> Result<[f32; 3], String> {
+    let parts: Vec<&str> = s.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!("expected three comma-separated numbers, got '{}'", s));
+    }
+    let mut v = [0.0_f32; 3];
+    for (x, p) in v.iter_mut().zip(&parts) {
+        *x = match p.trim().parse::<f32>() {
+            Ok(n) if n.is_finite() => n,
+            _ => return Err(format!("'{}' in '{}' is not a number", p.trim(), s)),
+        };
+    }
+    Ok(v)
+}
+
+// clap validator: a plain number, rejected up front rather than read as zero
+fn is_number(v: String) -> Result<(), String> {
+    match v.parse::<f32>() {
+        Ok(x) if x.is_finite() => Ok(()),
+        _ => Err(format!("expected a number, got '{}'", v)),
+    }
+}
+
+// clap validator: a length that can't be negative
+fn is_length(v: String) -> Result<(), String> {
+    match v.parse::<f32>() {
+        Ok(x) if x.is_finite() && x >= 0.0 => Ok(()),
+        _ => Err(format!("expected a length of zero or more, got '{}'", v)),
+    }
+}
+
+// clap validator: a cell index, rejected up front rather than read as cell 0
+fn is_index(v: String) -> Result<(), String> {
+    v.parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("expected a cell index, got '{}'", v))
 }
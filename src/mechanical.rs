@@ -27,79 +27,133 @@ impl Module {
     // create rectangular cell. yes, this is way too many parameters.
     // thermal and cycle life characteristics are assumed from cathode chemistry.
     pub fn new_rec_cell(l: f32, w: f32, h: f32, m: f32, r: f32, q: f32, chem: Chem) -> Module {
-        let (vmin, vnom, vmax, cycle_life, current_max_C, specific_heat, temp_max, temp_min) =
+        let (vmin, vnom, vmax, cycle_life, current_max_c, specific_heat, temp_max, temp_min) =
             defaults_from_chem(&chem);
-        return Module {
+        Module {
             shape: Shape::Prism,
             input_type: ModType::Cell,
-            chem: chem,
+            chem,
             series: 1,
             parallel: 1,
             dims: [l, w, h],
             mass: m,
-            termination: Term::Axial,
-            vmin: vmin,
-            vmax: vmax,
-            vnom: vnom,
-            q: q,
+            termination: Term::End,
+            vmin,
+            vmax,
+            vnom,
+            q,
             rnom: r,
-            max_current_continuous: current_max_C * q,
-            specific_heat: specific_heat,
-            cycle_life: cycle_life,
-            temp_max: temp_max,
-            temp_min: temp_min,
-        };
+            max_current_continuous: current_max_c * q,
+            specific_heat,
+            cycle_life,
+            temp_max,
+            temp_min,
+            temp_charge_min: None,
+            temp_charge_max: None,
+            temp_storage_min: None,
+            temp_storage_max: None,
+            swelling: None,
+            thickness_tolerance: 0.0,
+            volume: None,
+            holder: None,
+            runaway: None,
+            conductivity: None,
+        }
     }
 
     // create new cylindrical cell from basic params and chemistry.
-    pub fn new_cyl_cell(diam: f32, l: f32, m: f32, r: f32, v: f32, q: f32, chem: Chem) -> Module {
-        let (vmin, vnom, vmax, cycle_life, current_max_C, specific_heat, temp_max, temp_min) =
+    pub fn new_cyl_cell(diam: f32, l: f32, m: f32, r: f32, _v: f32, q: f32, chem: Chem) -> Module {
+        let (vmin, vnom, vmax, cycle_life, current_max_c, specific_heat, temp_max, temp_min) =
             defaults_from_chem(&chem);
-        return Module {
+        Module {
             shape: Shape::Cylinder,
             input_type: ModType::Cell,
-            chem: chem,
+            chem,
             series: 1,
             parallel: 1,
             dims: [diam, l, 0.0],
             mass: m,
             termination: Term::Axial,
-            vmin: vmin,
-            vmax: vmax,
-            vnom: vnom,
-            q: q,
+            vmin,
+            vmax,
+            vnom,
+            q,
             rnom: r,
-            max_current_continuous: current_max_C * q,
-            specific_heat: specific_heat,
-            cycle_life: cycle_life,
-            temp_max: temp_max,
-            temp_min: temp_min,
-        };
+            max_current_continuous: current_max_c * q,
+            specific_heat,
+            cycle_life,
+            temp_max,
+            temp_min,
+            temp_charge_min: None,
+            temp_charge_max: None,
+            temp_storage_min: None,
+            temp_storage_max: None,
+            swelling: None,
+            thickness_tolerance: 0.0,
+            volume: None,
+            holder: None,
+            runaway: None,
+            conductivity: None,
+        }
     }
 
     // returns volume in cubic meters
     pub fn get_volume(self) -> f32 {
         let vol: f32 = match self.shape {
             Shape::Cylinder => PI * (self.dims[0] / 2.0 * self.dims[0] / 2.0) * self.dims[1],
-            Shape::Prism => self.dims[0] * self.dims[1] * self.dims[2],
-            _ => 0.0,
+            Shape::Prism | Shape::Pouch => self.dims[0] * self.dims[1] * self.dims[2],
+            Shape::Custom | Shape::Other => self
+                .volume
+                .unwrap_or(self.dims[0] * self.dims[1] * self.dims[2]),
         };
-        return vol;
+        vol
     }
 
     // returns mass in kilograms.
     pub fn get_mass_kg(self) -> f32 {
-        return self.mass;
+        self.mass
     }
 
-    // basic cylindrical/prismatic packing parameters.
+    // center-to-center spacing of this module in the layout (x, y, z).
+    // hex-arranged cylinders sit in staggered columns, so neighbouring columns are only
+    // sqrt(3) / 2 of the cell spacing apart; square grids don't nest.
+    // prisms get a 2% gap along the stacking direction;
+    // pouches are stacked at their thickest allowed by tolerance.
+    // anything else packs as its bounding box.
+    pub fn get_pitch(&self) -> [f32; 3] {
+        let size = self.get_placed_size();
+        match self.shape {
+            Shape::Cylinder => {
+                let c = self.get_cell_spacing();
+                match self.get_arrangement() {
+                    Arrangement::Hex => [c * 3.0_f32.sqrt() / 2.0, c, size[2]],
+                    Arrangement::Square => [c, c, size[2]],
+                }
+            }
+            Shape::Prism => [size[0] / 0.98, size[1], size[2]],
+            Shape::Pouch => [size[0] + self.thickness_tolerance, size[1], size[2]],
+            Shape::Custom | Shape::Other => size,
+        }
+    }
+
+    // one terminal on each end (so series links alternate top and bottom faces)
+    pub fn is_axial(&self) -> bool {
+        matches!((self.shape, self.termination), (Shape::Cylinder, _) | (_, Term::Axial))
+    }
+
+    // basic prismatic packing parameters.
+    // cylinders, pouches and custom shapes take their pitch box: touching cylinders come
+    // out at pi / (2 sqrt 3) ~ 0.907 efficiency hex packed, pi / 4 ~ 0.785 on a square
+    // grid, and holder gaps bring both down further.
     pub fn get_min_volume_packed(self) -> f32 {
         let packing_eff: f32 = match self.shape {
-            Shape::Cylinder => 0.90,
             Shape::Prism => 0.98,
-            Shape::Other => 1.0,
+            Shape::Cylinder | Shape::Pouch | Shape::Custom | Shape::Other => {
+                let p = self.get_pitch();
+                return p[0] * p[1] * p[2];
+            }
         };
-        return (1.0 / packing_eff) * self.get_volume();
+        (1.0 / packing_eff) * self.get_volume()
     }
 }
 
@@ -107,24 +161,376 @@ impl Module {
 // Module arrays are an nxm array of modules only.
 impl Battery {
     pub fn get_min_volume_packed(&self) -> f32 {
-        let l = self.module_array.len();
-        let mut vol: f32 = 0.0;
-//        println!("~~~~this is the value of L: {}", l);
-        for i in 0..l {
-//            println!("self volume: {}", self.module_array[i].module.get_min_volume_packed());
-            let vp = self.module_array[i].module.get_min_volume_packed();
-//            println!("self count: {}", self.module_array[i].get_module_count());
-            let cc = self.module_array[i].get_module_count() as f32;
-            vol += vp * cc;
-        }
-        return vol;
+        self.module_array
+            .iter()
+            .map(|ma| ma.module.get_min_volume_packed() * ma.get_module_count() as f32)
+            .sum()
     }
 }
 
+pub const G: f32 = 9.80665;
+
 // Mechanical design requirements of the pack as a whole
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct MechanicalParams {
-    // linear and angular xyz
-    peak_accel: [f32; 6],
-    // fill in...
+    // linear xyz (m/s2) and angular xyz (rad/s2), pack frame
+    pub peak_accel: [f32; 6],
+    // number of pack mounting points sharing the load
+    pub mount_count: i32,
+    // rated capacity of one mount fastener in shear (x, y) and tension (z), N
+    pub fastener_shear: f32,
+    pub fastener_tension: f32,
+    // rated load of one mounting bracket in any direction, N
+    pub bracket_capacity: f32,
+    // retention force available per cell/module (holder, adhesive, clamp), N
+    pub cell_retention: f32,
+    // end plate and banding materials for prismatic stacks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixture: Option<FixtureParams>,
+}
+
+// standards-style load cases. these are design rules of thumb, not a substitute
+// for reading the standard.
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub enum LoadCase {
+    // ECE R100 annex 8C mechanical shock, M1/N1 vehicles: 20 g longitudinal
+    R100Longitudinal,
+    // ECE R100 annex 8C mechanical shock, M1/N1 vehicles: 8 g lateral
+    R100Lateral,
+    // vertical road shock (pothole, curb strike): 3 g on top of gravity
+    VerticalShock,
+    // ~3-sigma peak of ISO 12405 vertical random vibration (1.44 g RMS), plus gravity
+    Vibration,
+}
+
+impl LoadCase {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            LoadCase::R100Longitudinal => "r100-longitudinal",
+            LoadCase::R100Lateral => "r100-lateral",
+            LoadCase::VerticalShock => "vertical-shock",
+            LoadCase::Vibration => "vibration",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LoadCase> {
+        LoadCase::all()
+            .iter()
+            .find(|c| c.get_name() == name.to_lowercase())
+            .copied()
+    }
+
+    pub fn all() -> [LoadCase; 4] {
+        [
+            LoadCase::R100Longitudinal,
+            LoadCase::R100Lateral,
+            LoadCase::VerticalShock,
+            LoadCase::Vibration,
+        ]
+    }
+
+    // linear and angular acceleration for this case, same convention as peak_accel
+    pub fn get_accel(&self) -> [f32; 6] {
+        match self {
+            LoadCase::R100Longitudinal => [20.0 * G, 0.0, 0.0, 0.0, 0.0, 0.0],
+            LoadCase::R100Lateral => [0.0, 8.0 * G, 0.0, 0.0, 0.0, 0.0],
+            LoadCase::VerticalShock => [0.0, 0.0, 4.0 * G, 0.0, 0.0, 0.0],
+            LoadCase::Vibration => [0.0, 0.0, (1.0 + 3.0 * 1.44) * G, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+// one load path compared against its rated capacity
+#[derive(Clone, Debug)]
+pub struct LoadCheck {
+    pub name: String,
+    // newtons
+    pub load: f32,
+    pub capacity: f32,
+}
+
+impl LoadCheck {
+    // margin of safety: capacity / load - 1. negative means it fails.
+    pub fn get_margin(&self) -> f32 {
+        if self.load <= 0.0 {
+            return f32::INFINITY;
+        }
+        self.capacity / self.load - 1.0
+    }
+}
+
+impl MechanicalParams {
+    pub fn with_load_case(&self, case: LoadCase) -> MechanicalParams {
+        let mut p = *self;
+        p.peak_accel = case.get_accel();
+        p
+    }
+
+    // inertial loads on the pack mounts and on cell retention for each axis.
+    // mounts are assumed to share load evenly and to sit on the perimeter of the layout,
+    // so angular acceleration about axis r adds tau / (n * arm) along axis a, where arm is
+    // the half-extent of the layout along the remaining axis.
+    pub fn check_loads(&self, layout: &Layout) -> Vec<LoadCheck> {
+        let mp = layout.get_mass_properties();
+        let (lo, hi) = layout.get_bounds();
+        let half = [
+            0.5 * (hi[0] - lo[0]),
+            0.5 * (hi[1] - lo[1]),
+            0.5 * (hi[2] - lo[2]),
+        ];
+        let n = self.mount_count.max(1) as f32;
+        let axes = ["x", "y", "z"];
+        let mut checks: Vec<LoadCheck> = Vec::new();
+
+        for a in 0..3 {
+            let linear = mp.mass * self.peak_accel[a].abs() / n;
+            // rotation about the two other axes pushes mounts along this one
+            let mut angular: f32 = 0.0;
+            for r in 0..3 {
+                if r == a {
+                    continue;
+                }
+                let arm = half[3 - a - r];
+                if arm > 0.0 {
+                    angular += mp.inertia[r][r] * self.peak_accel[3 + r].abs() / (n * arm);
+                }
+            }
+            let load = linear + angular;
+            let fastener = if a == 2 {
+                self.fastener_tension
+            } else {
+                self.fastener_shear
+            };
+            checks.push(LoadCheck {
+                name: format!("mount fastener, {}", axes[a]),
+                load,
+                capacity: fastener,
+            });
+            checks.push(LoadCheck {
+                name: format!("mount bracket, {}", axes[a]),
+                load,
+                capacity: self.bracket_capacity,
+            });
+        }
+
+        // heaviest single cell/module governs retention
+        let m_cell = layout
+            .cells
+            .iter()
+            .map(|c| c.module.get_mass_kg())
+            .fold(0.0_f32, f32::max);
+        for (a, axis) in axes.iter().enumerate() {
+            checks.push(LoadCheck {
+                name: format!("cell retention, {}", axis),
+                load: m_cell * self.peak_accel[a].abs(),
+                capacity: self.cell_retention,
+            });
+        }
+        checks.retain(|c| c.load > 0.0);
+        checks
+    }
+}
+
+// mass, center of gravity and inertia tensor of a laid-out pack.
+// inertia is about the CoG, in the axes of whatever frame the CoG is given in. kg-m2.
+#[derive(Copy, Clone, Debug)]
+pub struct MassProperties {
+    pub mass: f32,
+    pub cog: [f32; 3],
+    pub inertia: [[f32; 3]; 3],
+}
+
+// where the pack frame sits in the vehicle frame.
+// origin: position of the pack frame origin in vehicle coordinates (m)
+// rpy: roll, pitch, yaw of the pack frame relative to the vehicle (rad), applied z-y-x.
+#[derive(Copy, Clone, Debug)]
+pub struct VehicleFrame {
+    pub origin: [f32; 3],
+    pub rpy: [f32; 3],
+}
+
+impl VehicleFrame {
+    // rotation matrix taking pack-frame vectors into the vehicle frame
+    pub fn get_rotation(&self) -> [[f32; 3]; 3] {
+        let (sr, cr) = self.rpy[0].sin_cos();
+        let (sp, cp) = self.rpy[1].sin_cos();
+        let (sy, cy) = self.rpy[2].sin_cos();
+        [
+            [cy * cp, cy * sp * sr - sy * cr, cy * sp * cr + sy * sr],
+            [sy * cp, sy * sp * sr + cy * cr, sy * sp * cr - cy * sr],
+            [-sp, cp * sr, cp * cr],
+        ]
+    }
+}
+
+// inertia of a solid cylinder standing along z, or a solid box, about its own center
+fn get_local_inertia(shape: Shape, m: f32, size: [f32; 3]) -> [[f32; 3]; 3] {
+    let (ixx, iyy, izz) = match shape {
+        Shape::Cylinder => {
+            let r = 0.5 * size[0];
+            let h = size[2];
+            let i_perp = m / 12.0 * (3.0 * r * r + h * h);
+            (i_perp, i_perp, 0.5 * m * r * r)
+        }
+        _ => {
+            let (a, b, c) = (size[0], size[1], size[2]);
+            (
+                m / 12.0 * (b * b + c * c),
+                m / 12.0 * (a * a + c * c),
+                m / 12.0 * (a * a + b * b),
+            )
+        }
+    };
+    [[ixx, 0.0, 0.0], [0.0, iyy, 0.0], [0.0, 0.0, izz]]
+}
+
+impl MassProperties {
+    // sum point-like elements (shape, mass, center, size) into one set of mass properties
+    fn from_elements(elements: &[(Shape, f32, [f32; 3], [f32; 3])]) -> MassProperties {
+        let mut mass: f32 = 0.0;
+        let mut moment = [0.0_f32; 3];
+        for (_, m, pos, _) in elements {
+            mass += m;
+            for a in 0..3 {
+                moment[a] += m * pos[a];
+            }
+        }
+        let mut cog = [0.0_f32; 3];
+        if mass > 0.0 {
+            for a in 0..3 {
+                cog[a] = moment[a] / mass;
+            }
+        }
+        // local inertia plus parallel axis term about the pack CoG
+        let mut inertia = [[0.0_f32; 3]; 3];
+        for (shape, m, pos, size) in elements {
+            let local = get_local_inertia(*shape, *m, *size);
+            let d = [pos[0] - cog[0], pos[1] - cog[1], pos[2] - cog[2]];
+            let d2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+            for i in 0..3 {
+                for j in 0..3 {
+                    let kron = if i == j { 1.0 } else { 0.0 };
+                    inertia[i][j] += local[i][j] + m * (d2 * kron - d[i] * d[j]);
+                }
+            }
+        }
+        MassProperties {
+            mass,
+            cog,
+            inertia,
+        }
+    }
+
+    // re-express CoG and inertia in the vehicle frame: cog' = o + R cog, I' = R I R^T
+    pub fn in_vehicle_frame(&self, frame: &VehicleFrame) -> MassProperties {
+        let r = frame.get_rotation();
+        let mut cog = frame.origin;
+        for (c, row) in cog.iter_mut().zip(r.iter()) {
+            *c += row[0] * self.cog[0] + row[1] * self.cog[1] + row[2] * self.cog[2];
+        }
+        let mut inertia = [[0.0_f32; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    for l in 0..3 {
+                        inertia[i][j] += r[i][k] * self.inertia[k][l] * r[j][l];
+                    }
+                }
+            }
+        }
+        MassProperties {
+            mass: self.mass,
+            cog,
+            inertia,
+        }
+    }
+
+    // inertia about an arbitrary point (same frame), via the parallel axis theorem
+    pub fn get_inertia_about(&self, point: [f32; 3]) -> [[f32; 3]; 3] {
+        let d = [
+            self.cog[0] - point[0],
+            self.cog[1] - point[1],
+            self.cog[2] - point[2],
+        ];
+        let d2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+        let mut inertia = self.inertia;
+        for i in 0..3 {
+            for j in 0..3 {
+                let kron = if i == j { 1.0 } else { 0.0 };
+                inertia[i][j] += self.mass * (d2 * kron - d[i] * d[j]);
+            }
+        }
+        inertia
+    }
+}
+
+impl Layout {
+    // cells are treated as homogeneous solids; components and busbars as solid boxes.
+    pub fn get_mass_properties(&self) -> MassProperties {
+        let mut elements: Vec<(Shape, f32, [f32; 3], [f32; 3])> = Vec::new();
+        for c in &self.cells {
+            elements.push((c.module.shape, c.module.get_mass_kg(), c.pos, c.size));
+        }
+        for c in &self.components {
+            elements.push((Shape::Prism, c.mass, c.pos, c.size));
+        }
+        for b in &self.busbars {
+            // pieces overlap; share the bar's mass out by piece area so it isn't counted twice
+            let share = b.get_mass() / b.get_piece_area().max(1e-12);
+            for (lo, hi) in &b.pieces {
+                let size = [hi[0] - lo[0], hi[1] - lo[1], b.thickness];
+                let pos = [
+                    0.5 * (lo[0] + hi[0]),
+                    0.5 * (lo[1] + hi[1]),
+                    b.z + 0.5 * b.thickness,
+                ];
+                let mass = size[0] * size[1] * share;
+                elements.push((Shape::Prism, mass, pos, size));
+            }
+        }
+        MassProperties::from_elements(&elements)
+    }
+}
+
+impl Battery {
+    pub fn get_mass_properties(&self) -> MassProperties {
+        self.get_layout().get_mass_properties()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 kg at the origin and 3 kg 2 m along x: CoG at x = 1.5, and about it
+    // Iyy = Izz = 1 * 1.5^2 + 3 * 0.5^2 = 3 kg-m2 while Ixx is zero
+    #[test]
+    fn two_points_known_answer() {
+        let point = [0.0; 3];
+        let mp = MassProperties::from_elements(&[
+            (Shape::Prism, 1.0, [0.0, 0.0, 0.0], point),
+            (Shape::Prism, 3.0, [2.0, 0.0, 0.0], point),
+        ]);
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        assert!(close(mp.mass, 4.0));
+        assert!(close(mp.cog[0], 1.5) && close(mp.cog[1], 0.0) && close(mp.cog[2], 0.0));
+        let same = |a: &[[f32; 3]; 3], b: [[f32; 3]; 3]| {
+            a.iter().flatten().zip(b.iter().flatten()).all(|(x, y)| close(*x, *y))
+        };
+        assert!(same(&mp.inertia, [[0.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, 3.0]]));
+
+        // yawed 90 degrees, pack x lies along vehicle y: the CoG moves to
+        // origin + (0, 1.5, 0) and Ixx and Iyy trade places
+        let frame = VehicleFrame {
+            origin: [1.0, 2.0, 3.0],
+            rpy: [0.0, 0.0, std::f32::consts::FRAC_PI_2],
+        };
+        let v = mp.in_vehicle_frame(&frame);
+        assert!(close(v.cog[0], 1.0) && close(v.cog[1], 3.5) && close(v.cog[2], 3.0));
+        assert!(same(&v.inertia, [[3.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 3.0]]));
+        // about the vehicle origin the CoG is (1, 3.5, 3) away: Izz gains 4 (1 + 12.25)
+        let about = v.get_inertia_about([0.0; 3]);
+        assert!(close(about[2][2], 3.0 + 4.0 * 13.25));
+        assert!(close(about[0][1], -4.0 * 3.5));
+    }
 }
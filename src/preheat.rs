@@ -0,0 +1,140 @@
+/*
+    cold weather: how long and how much energy it takes to warm a cold-soaked pack
+    up to where it may be charged, with a heater that runs off the pack itself.
+    the pack is one lumped node behind the insulation; good enough to compare
+    heater sizes and insulation.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// J/kg-K
+const COPPER_SPECIFIC_HEAT: f32 = 385.0;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PreheatParams {
+    // K
+    pub ambient_temp: f32,
+    // pack temperature when heating starts, K; the ambient if left out (fully soaked)
+    #[serde(default)]
+    pub soak_temp: Option<f32>,
+    // thermal resistance of the enclosure insulation, m2-K/W, over the outside of
+    // the cell block
+    pub insulation: f32,
+    // electrical power into the heater, W
+    pub heater_power: f32,
+    // heat out per electrical watt in; 1 for resistive heaters, more for a heat pump
+    #[serde(default)]
+    pub heater_efficiency: Option<f32>,
+    // vehicle energy use, Wh/km, to turn the heating energy into lost range
+    #[serde(default)]
+    pub consumption: Option<f32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Preheat {
+    // cells plus busbar copper, J/K
+    pub heat_capacity: f32,
+    // insulation loss to ambient, W/K
+    pub loss_conductance: f32,
+    pub start_temp: f32,
+    // warmest minimum charge temperature of any array, K
+    pub target_temp: f32,
+    // s and Wh taken from the pack
+    pub time: f32,
+    pub energy: f32,
+    // heater power needed to hold the pack at the target once there, W
+    pub hold_power: f32,
+    // km, if a consumption was given
+    pub range: Option<f32>,
+    // share of the pack's nominal energy, 0-1
+    pub pack_fraction: Option<f32>,
+}
+
+impl Module {
+    // lowest temperature the module may be charged at, K. LFP included, whatever its
+    // discharge range: below 0 C lithium plates out on charge.
+    pub fn get_temp_charge_min(&self) -> f32 {
+        self.get_operating_window().charge.0.kelvin()
+    }
+}
+
+impl Battery {
+    // the pack can only charge once every array can
+    pub fn get_temp_charge_min(&self) -> f32 {
+        self.module_array
+            .iter()
+            .map(|ma| ma.module.get_temp_charge_min())
+            .fold(f32::MIN, f32::max)
+    }
+
+    // J/K. other components don't carry a specific heat, so only the copper is counted.
+    pub fn get_heat_capacity(&self) -> f32 {
+        let cells: f32 = self
+            .module_array
+            .iter()
+            .map(|ma| ma.get_module_count() as f32 * ma.module.get_heat_capacity())
+            .sum();
+        let copper: f32 = self.get_layout().busbars.iter().map(|b| b.get_mass()).sum();
+        cells + copper * COPPER_SPECIFIC_HEAT
+    }
+
+    // lumped pack: C dT/dt = P - UA (T - Ta), so
+    // T(t) = Ta + P/UA + (T0 - Ta - P/UA) e^(-t/tau) with tau = C/UA.
+    // a heater that can't outrun the losses is Error::Infeasible.
+    pub fn analyze_preheat(&self, pp: &PreheatParams) -> Result<Preheat, Error> {
+        let (lo, hi) = self.get_layout().get_bounds();
+        let d = [hi[0] - lo[0], hi[1] - lo[1], hi[2] - lo[2]];
+        let area = 2.0 * (d[0] * d[1] + d[1] * d[2] + d[0] * d[2]);
+        let ua = area / pp.insulation.max(1e-6);
+        let c = self.get_heat_capacity();
+        let start = pp.soak_temp.unwrap_or(pp.ambient_temp);
+        let target = self.get_temp_charge_min();
+        let efficiency = pp.heater_efficiency.unwrap_or(1.0).max(1e-3);
+        let heat = pp.heater_power * efficiency;
+
+        let time = if start >= target {
+            0.0
+        } else {
+            // where the pack would level off with the heater on
+            let settle = pp.ambient_temp + heat / ua.max(1e-9);
+            if settle <= target {
+                return Err(Error::Infeasible(format!(
+                    "the heater can't outrun the insulation losses: the pack levels off at \
+                     {:.1} K, below its {:.1} K charging minimum",
+                    settle, target
+                )));
+            }
+            c / ua * ((settle - start) / (settle - target)).ln()
+        };
+        let energy = pp.heater_power * time / 3600.0;
+        let kwh = self.get_kwh_nominal();
+        Ok(Preheat {
+            heat_capacity: c,
+            loss_conductance: ua,
+            start_temp: start,
+            target_temp: target,
+            time,
+            energy,
+            hold_power: (ua * (target - pp.ambient_temp)).max(0.0) / efficiency,
+            range: pp.consumption.map(|w| energy / w.max(1e-6)),
+            pack_fraction: Some(kwh)
+                .filter(|k| *k > 0.0)
+                .map(|k| energy / (k * 1000.0)),
+        })
+    }
+}
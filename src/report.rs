@@ -0,0 +1,502 @@
+/*
+    presentation layer: every print-out the battgen CLI shows. binary-only, so the
+    library hands back numbers and never writes to the console itself.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use battgen::*;
+use colored::*;
+
+// user-facing print-out functions in the cmdline
+pub fn print_mass_properties(mp: &MassProperties) {
+    println!("Mass: {} kg", mp.mass);
+    println!(
+        "Center of gravity: ({}, {}, {}) m",
+        mp.cog[0], mp.cog[1], mp.cog[2]
+    );
+    println!("Inertia tensor about CoG (kg-m2):");
+    for row in mp.inertia.iter() {
+        println!("  [{:>12.5} {:>12.5} {:>12.5}]", row[0], row[1], row[2]);
+    }
+}
+
+pub fn print_routing(routing: &Routing) {
+    println!(
+        "Busbars: {} junctions, {:.2} m of strip, {:.3} kg copper, {:.1} uOhm in series",
+        routing.junctions,
+        routing.length,
+        routing.copper_mass,
+        routing.resistance * 1e6
+    );
+    if routing.crossings > 0 {
+        println!(
+            "{} {} pairs of busbars overlap on the same face",
+            "Warning:".red(),
+            routing.crossings
+        );
+    }
+}
+
+pub fn print_load_check(check: &LoadCheck) {
+    let margin = check.get_margin();
+    let m = format!("{:.2}", margin);
+    println!(
+        "  {:<24} load {:>10.1} N  capacity {:>10.1} N  margin {}",
+        check.name,
+        check.load,
+        check.capacity,
+        if margin < 0.0 { m.red() } else { m.green() }
+    );
+}
+
+pub fn print_coolant(coolant: &Coolant) {
+    println!("{} {}", "Coolant:".cyan(), coolant.name);
+    println!(
+        "Freezes at {:.1} K, boils at {:.1} K{}{}",
+        coolant.freezing_point,
+        coolant.boiling_point,
+        if coolant.flammable { ", flammable" } else { "" },
+        if coolant.conductive { ", electrically conductive" } else { "" }
+    );
+    println!("      T (K)  rho (kg/m3)  cp (J/kg-K)  mu (mPa-s)  k (W/m-K)     Pr");
+    for s in &coolant.table {
+        println!(
+            "  {:>9.2} {:>12.1} {:>12.0} {:>11.3} {:>10.4} {:>6.1}",
+            s.temp,
+            s.density,
+            s.specific_heat,
+            s.viscosity * 1000.0,
+            s.conductivity,
+            coolant.get_prandtl(s.temp)
+        );
+    }
+}
+
+pub fn print_cold_plate(plate: &ColdPlateDesign) {
+    println!("Heat to remove at design current: {:.0} W", plate.heat);
+    println!(
+        "Cold plate {:.0} x {:.0} mm: {:.3} m2 needed of {:.3} m2 under the cells",
+        plate.plate_size[0] * 1000.0,
+        plate.plate_size[1] * 1000.0,
+        plate.area_required,
+        plate.area_available
+    );
+    println!(
+        "{} channels {:.1} x {:.1} mm, {:.2} L/min, {:.2} m/s, Re {:.0}, h {:.0} W/m2-K",
+        plate.channels,
+        plate.channel_width * 1000.0,
+        plate.channel_height * 1000.0,
+        plate.flow_rate * 60000.0,
+        plate.velocity,
+        plate.reynolds,
+        plate.h
+    );
+    println!(
+        "Coolant outlet {:.1} K, hottest cell {:.1} K",
+        plate.outlet_temp, plate.cell_temp
+    );
+    println!(
+        "Pressure drop {:.2} kPa, pump power {:.2} W",
+        plate.pressure_drop / 1000.0,
+        plate.pump_power
+    );
+    if !plate.feasible {
+        println!(
+            "{} no channel layout keeps the cells under their limit",
+            "Warning:".red()
+        );
+    }
+}
+
+pub fn print_air_cooling(air: &AirCooling, temp_max: f32) {
+    let flag = |t: f32| {
+        let s = format!("{:.1} K", t);
+        if t > temp_max {
+            s.red()
+        } else {
+            s.green()
+        }
+    };
+    println!("Heat to remove at design current: {:.0} W", air.heat);
+    println!(
+        "Natural convection: h {:.1} W/m2-K, cells at {}",
+        air.natural_h,
+        flag(air.natural_cell_temp)
+    );
+    match air.required_flow {
+        Some((q, dp)) => println!("Forced air needed: {:.1} m3/h at {:.1} Pa", q * 3600.0, dp),
+        None => println!(
+            "{} no practical airflow holds the cells under {:.1} K",
+            "Warning:".red(),
+            temp_max
+        ),
+    }
+    if let Some((q, dp, t)) = air.fan_point {
+        println!(
+            "Fan operating point: {:.1} m3/h at {:.1} Pa, outlet-air cell estimate {}",
+            q * 3600.0,
+            dp,
+            flag(t)
+        );
+    }
+}
+
+pub fn print_preheat(preheat: &Preheat) {
+    println!(
+        "Pack heat capacity {:.1} kJ/K, insulation loss {:.2} W/K",
+        preheat.heat_capacity / 1000.0,
+        preheat.loss_conductance
+    );
+    println!(
+        "Warming from {:.1} K to the {:.1} K charging minimum",
+        preheat.start_temp, preheat.target_temp
+    );
+    println!(
+        "Heater on for {:.1} min, {:.0} Wh from the pack",
+        preheat.time / 60.0,
+        preheat.energy
+    );
+    if let Some(f) = preheat.pack_fraction {
+        println!("That is {:.1}% of nominal pack energy", f * 100.0);
+    }
+    if let Some(km) = preheat.range {
+        println!("Range cost: {:.1} km", km);
+    }
+    println!("Holding at charging minimum takes {:.0} W", preheat.hold_power);
+}
+
+pub fn print_gradient(g: &ThermalGradient) {
+    let (lo, hi) = g.get_extremes();
+    let spread = g.get_spread();
+    println!(
+        "{:.0} W into a {:?} plate at {:.1} K, {} of {} cells touching it",
+        g.heat,
+        g.plate,
+        g.plate_temp,
+        g.cooled_cells,
+        g.cell_temps.len()
+    );
+    println!(
+        "Coolest cell {} at {:.1} K, hottest cell {} at {:.1} K",
+        lo, g.cell_temps[lo], hi, g.cell_temps[hi]
+    );
+    let s = format!("{:.1} K", spread);
+    println!(
+        "Cell-to-cell spread: {} (limit {:.0} K)",
+        if spread > SPREAD_LIMIT { s.red() } else { s.green() },
+        SPREAD_LIMIT
+    );
+    println!(
+        "Largest spread inside one cell: {:.1} K above its mean",
+        g.get_internal_spread()
+    );
+    println!(
+        "Reaches steady state in roughly {:.0} min",
+        g.time_constant / 60.0
+    );
+}
+
+pub fn print_finding(f: &Finding, array: usize) {
+    let tag = match f.severity {
+        Severity::Error => "Error:".red(),
+        Severity::Warning => "Warning:".yellow(),
+    };
+    println!("{} module array {} {}: {}", tag, array, f.field, f.message);
+}
+
+pub fn print_imported_cell(cell: &ImportedCell) {
+    let m = &cell.module;
+    println!(
+        "{}: {:?} {:.2} V {:.2} Ah, {:.3} kg, {:.4?} m",
+        cell.name.green(),
+        m.chem,
+        m.vnom,
+        m.q,
+        m.mass,
+        m.dims
+    );
+    if !cell.source.is_empty() {
+        println!("  from: {}", cell.source);
+    }
+    let defaulted = cell.get_defaulted();
+    if !defaulted.is_empty() {
+        println!(
+            "  {} {}",
+            "not on the datasheet, defaults used:".yellow(),
+            defaulted.join(", ")
+        );
+    }
+    let zeroed = cell.get_zeroed();
+    if !zeroed.is_empty() {
+        println!(
+            "  {} {}",
+            "not on the datasheet, set to 0; fill in:".red(),
+            zeroed.join(", ")
+        );
+    }
+    // there's no pack yet, so no module array to name
+    for f in m.validate() {
+        let tag = match f.severity {
+            Severity::Error => "Error:".red(),
+            Severity::Warning => "Warning:".yellow(),
+        };
+        println!("  {} {}: {}", tag, f.field, f.message);
+    }
+}
+
+pub fn print_operating_window(w: &OperatingWindow) {
+    for (name, (lo, hi)) in [
+        ("charge", w.charge),
+        ("discharge", w.discharge),
+        ("storage", w.storage),
+    ]
+    .iter()
+    {
+        println!("  {:<10} {:>6.1} to {:>5.1} C", name, lo.celsius(), hi.celsius());
+    }
+}
+
+pub fn print_violation(v: &LimitViolation) {
+    let side = if v.worst < v.limit { "under" } else { "over" };
+    println!(
+        "{} module array {} {:?} {} {:.1} C limit from {:.0} to {:.0} s, worst {:.1} C",
+        "Warning:".red(),
+        v.array,
+        v.mode,
+        side,
+        v.limit.celsius(),
+        v.start,
+        v.end,
+        v.worst.celsius()
+    );
+}
+
+pub fn print_propagation(p: &Propagation) {
+    println!(
+        "{} cell {} of {}, {} gaps",
+        "Thermal runaway starting in".cyan(),
+        p.trigger,
+        p.cells,
+        p.gap.name
+    );
+    match p.get_first_propagation() {
+        None => println!(
+            "{} hottest neighbour peaks at {:.0} K",
+            "Contained:".green(),
+            p.peak_survivor_temp
+        ),
+        Some(t) => {
+            println!(
+                "{} first neighbour after {:.0} s, {} of {} cells by {:.0} s ({:.1} cells/min)",
+                "Propagates:".red(),
+                t,
+                p.events.len(),
+                p.cells,
+                p.events[p.events.len() - 1].1,
+                p.get_rate()
+            );
+        }
+    }
+}
+
+pub fn print_barrier(p: &Propagation) {
+    let verdict = if p.is_contained() {
+        "stops it".green()
+    } else {
+        format!("{} cells", p.events.len()).red()
+    };
+    println!(
+        "  {:<28} k {:>5.3} W/m-K: {:<10} hottest survivor {:>5.0} K",
+        p.gap.name, p.gap.conductivity, verdict, p.peak_survivor_temp
+    );
+}
+
+pub fn print_pack_topology(b: &Battery) {
+    let a = b.get_topology();
+    println!("{} {}S{}P", "Battery minimum topology: ".purple(), a.0, a.1);
+    println!("Note: this does not account for cell voltages.");
+}
+
+pub fn print_pack_voltage(b: &Battery) {
+    println!("Pack voltage: {}V", b.get_voltage());
+}
+
+pub fn print_pack_ah(b: &Battery) {
+    println!("Pack capacity: {}Ah", b.get_ah());
+}
+
+pub fn print_pack_overview(b: &Battery) {
+    for (i, ma) in b.module_array.iter().enumerate() {
+        println!(
+            "Module array {}: {}S{}P, {:.1} V, {:.1} Ah",
+            i,
+            ma.series,
+            ma.parallel,
+            ma.get_voltage(),
+            ma.get_ah()
+        );
+    }
+    print_pack_topology(b);
+    print_pack_voltage(b);
+    print_pack_ah(b);
+    println!("Pack energy: {:.2} kWh nominal", b.get_kwh_nominal());
+    let layout = b.get_layout();
+    println!(
+        "Pack mass: {:.2} kg, bounding volume {:.4} m3 (cells packed {:.4} m3)",
+        b.get_mass_properties().mass,
+        layout.get_bounding_volume(),
+        layout.get_packed_volume()
+    );
+    println!(
+        "Pack DC resistance at 50% SoC incl. busbars: {:.2} mOhm",
+        b.get_ir_dc_total(&0.5) * 1000.0
+    );
+}
+
+pub fn print_limits(b: &Battery, traces: &[ThermalTrace]) {
+    for (i, ma) in b.module_array.iter().enumerate() {
+        println!("Module array {} operating window:", i);
+        print_operating_window(&ma.module.get_operating_window());
+    }
+    let violations = b.check_operating_window(traces);
+    if violations.is_empty() {
+        println!("{}", "Every module stays inside its temperature limits.".green());
+    }
+    for v in violations {
+        print_violation(&v);
+    }
+}
+
+pub fn print_heat(b: &Battery, current: f32, temp: f32) {
+    println!(
+        "Pack heat at {} A and {} K (W); entropic heat is negative when absorbed:",
+        current, temp
+    );
+    println!("   SoC      I2R   entropic dis.  total dis.   entropic chg.  total chg.");
+    for k in 0..=10 {
+        let soc = k as f32 / 10.0;
+        let irr = b.get_heat_generation(current, soc, 0.0);
+        let dis = b.get_heat_generation(current, soc, temp);
+        let chg = b.get_heat_generation(-current, soc, temp);
+        println!(
+            "  {:>3.0}% {:>8.1} {:>14.1} {:>11.1} {:>15.1} {:>11.1}",
+            soc * 100.0,
+            irr,
+            dis - irr,
+            dis,
+            chg - irr,
+            chg
+        );
+    }
+}
+
+pub fn print_trace(t: &ThermalTrace) {
+    let last = t.points[t.points.len() - 1];
+    println!(
+        "Module array {}: {:.0} s, final SoC {:.1}%, final {:.1} K, peak {:.1} K (max {:.1} K)",
+        t.array,
+        last.time,
+        last.soc * 100.0,
+        last.temp,
+        t.get_peak_temp(),
+        t.temp_max
+    );
+    if let Some(t) = t.get_violation() {
+        println!("{} temp_max exceeded at {:.0} s", "Warning:".red(), t);
+    }
+    if t.exceeds_soc() {
+        println!(
+            "{} profile runs the pack past empty or full",
+            "Warning:".red()
+        );
+    }
+}
+
+pub fn print_holder(h: &HolderDesign) {
+    println!(
+        "Cell holder for {} cells ({:?}): two plates {:.1} x {:.1} x {:.1} mm, {:.3} kg total",
+        h.cells,
+        h.arrangement,
+        h.plate_size[0] * 1000.0,
+        h.plate_size[1] * 1000.0,
+        h.plate_size[2] * 1000.0,
+        h.mass
+    );
+}
+
+pub fn print_fixture(d: &FixtureDesign) {
+    println!(
+        "Stack of {} cells, {:.1} mm at BOL and 0% SoC",
+        d.cells,
+        d.stack_thickness * 1000.0
+    );
+    println!("Required preload: {:.0} N", d.preload);
+    println!("End plate thickness: {:.1} mm", d.plate_thickness * 1000.0);
+    println!("Strap cross-section (each): {:.1} mm2", d.strap_area * 1e6);
+    println!("Force at end of life: {:.0} N", d.eol_force);
+    for st in &d.states {
+        println!(
+            "  {} SoC {:>3.0}%: free stack {:>7.2} mm, force {:>8.0} N, pressure {:>6.1} kPa",
+            if st.eol { "EOL" } else { "BOL" },
+            st.soc * 100.0,
+            st.free_thickness * 1000.0,
+            st.force,
+            st.pressure / 1000.0
+        );
+    }
+    if d.is_overpressure() {
+        println!(
+            "{} stack pressure reaches {:.1} kPa, over the cell's {:.1} kPa limit; \
+             the fixture is too stiff for this much swelling",
+            "Warning:".red(),
+            d.peak_pressure / 1000.0,
+            d.max_pressure / 1000.0
+        );
+    }
+}
+
+pub fn print_module_overview(m: &Module) {
+    print_module_mechanical(m);
+    print_module_electrical(m);
+    print_module_mass(m);
+    print_module_topology(m);
+}
+
+pub fn print_module_mechanical(m: &Module) {
+    println!("Volume of cells in module: {} m3", m.get_volume());
+    println!(
+        "Volume with packing efficiency: {} m3",
+        m.get_min_volume_packed()
+    );
+}
+
+pub fn print_module_mass(m: &Module) {
+    println!("Module cell mass: {} kg", m.get_mass_kg());
+}
+
+pub fn print_module_topology(m: &Module) {
+    println!("Module topology: {}S{}P", m.series, m.parallel);
+}
+
+pub fn print_module_electrical(m: &Module) {
+    println!(
+        "Module nominal characteristics: {}V, {}Ah, {}kWh",
+        m.get_voltage(),
+        m.get_ah(),
+        m.get_kwh_nominal()
+    );
+}
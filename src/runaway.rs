@@ -0,0 +1,351 @@
+/*
+    thermal runaway propagation: a coarse nodal model over the layout.
+    one cell is forced into runaway; every cell is one isothermal node, linked to
+    its neighbours by conduction through whatever fills the gap (plus radiation when
+    that's air) and to still ambient air. a cell that reaches its onset temperature
+    runs away too and dumps its runaway heat over a fixed time.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+const STEFAN_BOLTZMANN: f32 = 5.670e-8;
+// two facing cell cans at emissivity 0.8
+const GAP_EMISSIVITY: f32 = 0.667;
+// still air around the outside of the cells, W/m2-K
+const RUNAWAY_AMBIENT_H: f32 = 10.0;
+// anything thinner is treated as this much contact resistance, m
+const MIN_GAP: f32 = 0.2e-3;
+// cans touching each other (no gap in the layout, no barrier): metal contact through
+// oxide and shrink sleeve, as an equivalent conductivity over MIN_GAP, W/m-K
+const CONTACT_CONDUCTIVITY: f32 = 1.0;
+// longest a propagation run is followed after the last cell went, s. counting from the
+// trigger instead cut slow runs through big packs short.
+const RUNAWAY_WINDOW: f32 = 1800.0;
+
+// optional cell file parameter; defaults come from the chemistry (see Module::get_runaway)
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Runaway {
+    // self-heating turns into runaway here
+    pub onset_temp: Temperature,
+    // heat that stays in the cell body, J per Wh of stored energy
+    pub energy: f32,
+    // how long the cell takes to release it, s
+    pub duration: f32,
+}
+
+// what fills the gap between neighbouring cells
+#[derive(Clone, Debug)]
+pub struct GapMaterial {
+    pub name: String,
+    // W/m-K, at runaway-ish temperatures
+    pub conductivity: f32,
+    // m; zero keeps the layout's own gaps
+    pub thickness: f32,
+    // transparent to infrared (only true for air)
+    pub radiative: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Propagation {
+    pub trigger: usize,
+    pub gap: GapMaterial,
+    // (cell index, time it went into runaway s), in order; the trigger is first at t = 0
+    pub events: Vec<(usize, f32)>,
+    // hottest any cell that didn't run away got, K
+    pub peak_survivor_temp: f32,
+    pub cells: usize,
+}
+
+// barriers offered when checking what would stop propagation
+pub fn gap_materials() -> Vec<GapMaterial> {
+    let m = |name: &str, conductivity: f32, thickness: f32, radiative: bool| GapMaterial {
+        name: name.to_string(),
+        conductivity,
+        thickness,
+        radiative,
+    };
+    vec![
+        m("air", 0.05, 0.0, true),
+        m("thermal gap filler 1 mm", 2.0, 1e-3, false),
+        m("silicone foam 2 mm", 0.1, 2e-3, false),
+        m("mica sheet 0.5 mm", 0.5, 0.5e-3, false),
+        m("intumescent 1 mm", 0.15, 1e-3, false),
+        m("ceramic fiber paper 1 mm", 0.08, 1e-3, false),
+        m("aerogel blanket 1 mm", 0.025, 1e-3, false),
+        m("aerogel blanket 2 mm", 0.025, 2e-3, false),
+    ]
+}
+
+impl Module {
+    // cell file values if given, otherwise rough chemistry defaults.
+    // onset is where calorimetry shows self-heating starting (80-120 C for the oxides),
+    // which is conservative next to the temperature the cell actually vents and burns
+    // at; a node model that waits for the latter never sees a neighbour go, since the
+    // heat it takes in from one side is spread over its whole mass. energy is what
+    // stays in the can after venting.
+    pub fn get_runaway(&self) -> Runaway {
+        if let Some(r) = self.runaway {
+            return r;
+        }
+        let (onset_temp, energy, duration) = match self.chem {
+            Chem::NCA => (358.15, 3300.0, 10.0),
+            Chem::NMC => (363.15, 3000.0, 15.0),
+            Chem::LMO => (373.15, 2000.0, 20.0),
+            Chem::LFP => (393.15, 1000.0, 40.0),
+            Chem::LTO => (413.15, 600.0, 60.0),
+            Chem::NiMH => (433.15, 300.0, 60.0),
+            Chem::Other => (358.15, 3300.0, 10.0),
+        };
+        Runaway {
+            onset_temp: Temperature::from_kelvin(onset_temp),
+            energy,
+            duration,
+        }
+    }
+
+    // J released into the cell body by one runaway
+    pub fn get_runaway_energy(&self) -> f32 {
+        self.get_runaway().energy * self.get_kwh_nominal() * 1000.0
+    }
+}
+
+impl Propagation {
+    pub fn is_contained(&self) -> bool {
+        self.events.len() <= 1
+    }
+
+    // time until the first neighbour went, s
+    pub fn get_first_propagation(&self) -> Option<f32> {
+        self.events.get(1).map(|e| e.1)
+    }
+
+    // cells per minute, averaged over the whole event
+    pub fn get_rate(&self) -> f32 {
+        match self.events.last() {
+            Some(&(_, t)) if self.events.len() > 1 && t > 0.0 => {
+                (self.events.len() - 1) as f32 * 60.0 / t
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+impl Layout {
+    // (cell a, cell b, conductance per W/m-K of gap fill (m), radiating area m2, gap m)
+    // between every pair of touching or nearly touching cells. `thickness` overrides
+    // the gap the layout left, as if a barrier of that thickness were put in.
+    pub fn get_thermal_links(&self, thickness: f32) -> Vec<(usize, usize, f32, f32, f32)> {
+        let mut links = Vec::new();
+        for (a, ca) in self.cells.iter().enumerate() {
+            for (b, cb) in self.cells.iter().enumerate().skip(a + 1) {
+                let height = ca.size[2].min(cb.size[2]);
+                let cyl = matches!(ca.module.shape, Shape::Cylinder)
+                    && matches!(cb.module.shape, Shape::Cylinder);
+                if cyl {
+                    let r = 0.25 * (ca.size[0] + cb.size[0]);
+                    let dx = ca.pos[0] - cb.pos[0];
+                    let dy = ca.pos[1] - cb.pos[1];
+                    let gap = (dx * dx + dy * dy).sqrt() - 2.0 * r;
+                    if gap > 0.25 * r {
+                        continue;
+                    }
+                    let gap = if thickness > 0.0 { thickness } else { gap }.max(MIN_GAP);
+                    // conduction shape factor between parallel cylinders
+                    let d = 2.0 * r + gap;
+                    let s = 2.0 * PI * height / (d * d / (2.0 * r * r) - 1.0).acosh();
+                    links.push((a, b, s, PI * 2.0 * r * height / 6.0, gap));
+                } else {
+                    let gx = (ca.pos[0] - cb.pos[0]).abs() - 0.5 * (ca.size[0] + cb.size[0]);
+                    let gy = (ca.pos[1] - cb.pos[1]).abs() - 0.5 * (ca.size[1] + cb.size[1]);
+                    let tol = 0.25 * ca.size[0].min(ca.size[1]);
+                    // facing along whichever axis they're separated on, overlapping on the other
+                    let (gap, overlap) = if gx > gy { (gx, -gy) } else { (gy, -gx) };
+                    if gap > tol || overlap <= 0.0 {
+                        continue;
+                    }
+                    let gap = if thickness > 0.0 { thickness } else { gap }.max(MIN_GAP);
+                    let area = overlap * height;
+                    links.push((a, b, area / gap, area, gap));
+                }
+            }
+        }
+        links
+    }
+
+    // cell with the most neighbours, nearest the middle on ties: the worst place to start
+    pub fn get_runaway_trigger(&self) -> usize {
+        let links = self.get_thermal_links(0.0);
+        let mut count = vec![0usize; self.cells.len()];
+        for l in &links {
+            count[l.0] += 1;
+            count[l.1] += 1;
+        }
+        let (lo, hi) = self.get_bounds();
+        let mid = [0.5 * (lo[0] + hi[0]), 0.5 * (lo[1] + hi[1])];
+        let off = |c: &CellPlacement| (c.pos[0] - mid[0]).powi(2) + (c.pos[1] - mid[1]).powi(2);
+        (0..self.cells.len())
+            .max_by(|&a, &b| {
+                count[a]
+                    .cmp(&count[b])
+                    .then(off(&self.cells[b]).total_cmp(&off(&self.cells[a])))
+            })
+            .unwrap_or(0)
+    }
+
+    // follows a runaway started in `trigger` until RUNAWAY_WINDOW seconds pass without
+    // another cell going, or nothing is releasing heat and the rest of the pack has
+    // stopped warming up. a trigger past the last cell is Error::Infeasible.
+    pub fn simulate_runaway(
+        &self,
+        trigger: usize,
+        gap: &GapMaterial,
+        ambient: f32,
+    ) -> Result<Propagation, Error> {
+        let n = self.cells.len();
+        if trigger >= n {
+            return Err(Error::Infeasible(format!(
+                "can't start runaway in cell {}: the pack has {} cells (0 to {})",
+                trigger,
+                n,
+                n.saturating_sub(1)
+            )));
+        }
+        let links = self.get_thermal_links(gap.thickness);
+        let cap: Vec<f32> = self
+            .cells
+            .iter()
+            .map(|c| c.module.get_heat_capacity().max(1e-6))
+            .collect();
+        let loss: Vec<f32> = self
+            .cells
+            .iter()
+            .map(|c| RUNAWAY_AMBIENT_H * c.module.get_surface_area())
+            .collect();
+        let runaway: Vec<Runaway> = self.cells.iter().map(|c| c.module.get_runaway()).collect();
+        let power: Vec<f32> = self
+            .cells
+            .iter()
+            .zip(&runaway)
+            .map(|(c, r)| c.module.get_runaway_energy() / r.duration.max(1.0))
+            .collect();
+
+        // explicit Euler; keep the step well under the fastest node's time constant,
+        // counting radiation at ~1000 K
+        let k = |gap_m: f32| {
+            if gap.thickness <= 0.0 && gap_m <= MIN_GAP {
+                CONTACT_CONDUCTIVITY
+            } else {
+                gap.conductivity
+            }
+        };
+        let mut g_sum = loss.clone();
+        for &(a, b, s, area, gap_m) in &links {
+            let mut g = k(gap_m) * s;
+            if gap.radiative {
+                g += 4.0 * STEFAN_BOLTZMANN * GAP_EMISSIVITY * area * 1e9;
+            }
+            g_sum[a] += g;
+            g_sum[b] += g;
+        }
+        let tau = (0..n).map(|i| cap[i] / g_sum[i]).fold(f32::MAX, f32::min);
+        let dt = (0.2 * tau).clamp(1e-3, 0.5);
+
+        let mut temp = vec![ambient; n];
+        let mut started: Vec<Option<f32>> = vec![None; n];
+        let mut max_temp = vec![ambient; n];
+        temp[trigger] = runaway[trigger].onset_temp.kelvin();
+        started[trigger] = Some(0.0);
+        let mut events = vec![(trigger, 0.0)];
+        let (mut t, mut last_rise, mut warmest) = (0.0_f32, 0.0_f32, ambient);
+        let mut last_event = 0.0_f32;
+        let mut q = vec![0.0_f32; n];
+        while t - last_event < RUNAWAY_WINDOW {
+            for (i, qi) in q.iter_mut().enumerate() {
+                let releasing = started[i].is_some_and(|s| t - s < runaway[i].duration);
+                *qi = if releasing { power[i] } else { 0.0 } - loss[i] * (temp[i] - ambient);
+            }
+            for &(a, b, s, area, gap_m) in &links {
+                let mut f = k(gap_m) * s * (temp[a] - temp[b]);
+                if gap.radiative {
+                    f += STEFAN_BOLTZMANN
+                        * GAP_EMISSIVITY
+                        * area
+                        * (temp[a].powi(4) - temp[b].powi(4));
+                }
+                q[a] -= f;
+                q[b] += f;
+            }
+            t += dt;
+            let mut releasing = false;
+            let mut peak = ambient;
+            for i in 0..n {
+                temp[i] += dt * q[i] / cap[i];
+                max_temp[i] = max_temp[i].max(temp[i]);
+                match started[i] {
+                    None if temp[i] >= runaway[i].onset_temp.kelvin() => {
+                        started[i] = Some(t);
+                        events.push((i, t));
+                        last_event = t;
+                        releasing = true;
+                    }
+                    None => peak = peak.max(temp[i]),
+                    Some(s) => releasing |= t - s < runaway[i].duration,
+                }
+            }
+            if peak > warmest + 1e-3 {
+                warmest = peak;
+                last_rise = t;
+            }
+            if !releasing && t - last_rise > 120.0 {
+                break;
+            }
+        }
+        Ok(Propagation {
+            trigger,
+            gap: gap.clone(),
+            events,
+            peak_survivor_temp: (0..n)
+                .filter(|&i| started[i].is_none())
+                .map(|i| max_temp[i])
+                .fold(ambient, f32::max),
+            cells: n,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // touching NMC cylinders carry a runaway through a block in air, as in the
+    // touching-cell tests on 18650s; 2 mm of aerogel between them stops it
+    #[test]
+    fn touching_cylinders_propagate() {
+        let mut cell = read_cell("m50t_21700", std::path::Path::new("")).unwrap();
+        cell.holder = None;
+        let layout = Battery::new_from(cell, 4, 4).get_layout();
+        let trigger = layout.get_runaway_trigger();
+        let materials = gap_materials();
+        let air = layout.simulate_runaway(trigger, &materials[0], 298.15).unwrap();
+        assert!(!air.is_contained());
+        assert_eq!(air.events.len(), air.cells);
+        let aerogel = materials.iter().find(|m| m.name == "aerogel blanket 2 mm").unwrap();
+        assert!(layout.simulate_runaway(trigger, aerogel, 298.15).unwrap().is_contained());
+        assert!(layout.simulate_runaway(layout.cells.len(), aerogel, 298.15).is_err());
+    }
+}
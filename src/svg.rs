@@ -0,0 +1,169 @@
+/*
+    top-view drawings of a Layout for assembly instructions:
+    cell outlines, polarity, series group numbers and busbars.
+    drawn straight from the placement data, so it always matches the volume numbers.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+use std::fmt::Write;
+
+// longest side of the drawing area, px
+const SVG_SIZE: f32 = 1200.0;
+const SVG_MARGIN: f32 = 40.0;
+
+impl Layout {
+    // top view, looking down -z. x to the right, y up the page.
+    // top-face busbars are filled, bottom-face busbars are dashed outlines.
+    pub fn to_svg(&self) -> String {
+        let (lo, hi) = self.get_bounds();
+        let (w, h) = ((hi[0] - lo[0]).max(1e-3), (hi[1] - lo[1]).max(1e-3));
+        let scale = SVG_SIZE / w.max(h);
+        let px = |x: f32| SVG_MARGIN + (x - lo[0]) * scale;
+        let py = |y: f32| SVG_MARGIN + (hi[1] - y) * scale;
+        let (width, height) = (w * scale + 2.0 * SVG_MARGIN, h * scale + 3.0 * SVG_MARGIN);
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" \
+             viewBox=\"0 0 {:.0} {:.0}\" font-family=\"sans-serif\">",
+            width, height, width, height
+        )
+        .unwrap();
+        writeln!(out, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>").unwrap();
+
+        // bottom busbars first so cells draw over them
+        writeln!(out, "<g id=\"busbars-bottom\" fill=\"none\" stroke=\"#b87333\" stroke-width=\"2\" stroke-dasharray=\"6,4\">").unwrap();
+        for b in self.busbars.iter().filter(|b| !b.top) {
+            for (l, u) in &b.pieces {
+                svg_rect(&mut out, px(l[0]), py(u[1]), (u[0] - l[0]) * scale, (u[1] - l[1]) * scale);
+            }
+        }
+        writeln!(out, "</g>").unwrap();
+
+        writeln!(out, "<g id=\"cells\" fill=\"#dce6f0\" stroke=\"#335\" stroke-width=\"1\">").unwrap();
+        for c in &self.cells {
+            let (cx, cy) = (px(c.pos[0]), py(c.pos[1]));
+            match c.module.shape {
+                Shape::Cylinder => {
+                    writeln!(
+                        out,
+                        "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\"/>",
+                        cx,
+                        cy,
+                        0.5 * c.size[0] * scale
+                    )
+                    .unwrap();
+                }
+                _ => {
+                    let (sw, sh) = (c.size[0] * scale, c.size[1] * scale);
+                    svg_rect(&mut out, cx - 0.5 * sw, cy - 0.5 * sh, sw, sh);
+                }
+            }
+        }
+        writeln!(out, "</g>").unwrap();
+
+        writeln!(out, "<g id=\"busbars-top\" fill=\"#b87333\" fill-opacity=\"0.55\" stroke=\"#8a5526\">").unwrap();
+        for b in self.busbars.iter().filter(|b| b.top) {
+            for (l, u) in &b.pieces {
+                svg_rect(&mut out, px(l[0]), py(u[1]), (u[0] - l[0]) * scale, (u[1] - l[1]) * scale);
+            }
+        }
+        writeln!(out, "</g>").unwrap();
+
+        // polarity: what you see on the top face
+        writeln!(out, "<g id=\"polarity\" text-anchor=\"middle\" dominant-baseline=\"central\" font-weight=\"bold\">").unwrap();
+        for c in &self.cells {
+            if c.module.is_axial() {
+                let fs = 0.45 * c.size[0].min(c.size[1]) * scale;
+                svg_sign(&mut out, px(c.pos[0]), py(c.pos[1]), fs, !c.flip);
+            } else {
+                let fs = (0.6 * c.size[0] * scale).min(0.2 * c.size[1] * scale);
+                let (pos, neg) = c.get_terminals();
+                svg_sign(&mut out, px(pos[0]), py(pos[1]), fs, true);
+                svg_sign(&mut out, px(neg[0]), py(neg[1]), fs, false);
+            }
+        }
+        writeln!(out, "</g>").unwrap();
+
+        writeln!(out, "<g id=\"groups\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"#003\" stroke=\"white\" stroke-width=\"3\" paint-order=\"stroke\">").unwrap();
+        for g in 0..self.get_group_count() {
+            let cells = self.get_group(g);
+            if cells.is_empty() {
+                continue;
+            }
+            let n = cells.len() as f32;
+            let x = cells.iter().map(|c| c.pos[0]).sum::<f32>() / n;
+            let y = cells.iter().map(|c| c.pos[1]).sum::<f32>() / n;
+            let fs = (0.5 * cells[0].size[0] * scale).clamp(8.0, 24.0);
+            writeln!(
+                out,
+                "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"{:.1}\">S{}</text>",
+                px(x),
+                py(y),
+                fs,
+                g + 1
+            )
+            .unwrap();
+        }
+        writeln!(out, "</g>").unwrap();
+
+        writeln!(
+            out,
+            "<text x=\"{:.0}\" y=\"{:.0}\" font-size=\"14\">{} cells, {} series groups, \
+             {:.0} x {:.0} x {:.0} mm, bounding volume {:.4} m3 (cells packed {:.4} m3). \
+             top busbars filled, bottom dashed.</text>",
+            SVG_MARGIN,
+            height - SVG_MARGIN,
+            self.cells.len(),
+            self.get_group_count(),
+            w * 1000.0,
+            h * 1000.0,
+            (hi[2] - lo[2]) * 1000.0,
+            self.get_bounding_volume(),
+            self.get_packed_volume()
+        )
+        .unwrap();
+        writeln!(out, "</svg>").unwrap();
+        out
+    }
+}
+
+fn svg_rect(out: &mut String, x: f32, y: f32, w: f32, h: f32) {
+    writeln!(
+        out,
+        "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"/>",
+        x, y, w, h
+    )
+    .unwrap();
+}
+
+fn svg_sign(out: &mut String, x: f32, y: f32, size: f32, positive: bool) {
+    let (sign, color) = if positive {
+        ("+", "#c00")
+    } else {
+        ("&#8722;", "#000")
+    };
+    writeln!(
+        out,
+        "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"{:.1}\" fill=\"{}\">{}</text>",
+        x, y, size, color, sign
+    )
+    .unwrap();
+}
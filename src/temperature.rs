@@ -0,0 +1,309 @@
+/*
+    temperatures that know their units, and the operating window a module has to stay in
+    while charging, discharging and sitting in storage.
+    internally everything is Kelvin; Celsius only exists at the edges (cell files, print-outs).
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::Serializer;
+use std::fmt;
+
+const ZERO_CELSIUS: f32 = 273.15;
+
+// a pack current below this (A) counts as resting
+const REST_CURRENT: f32 = 1e-3;
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Temperature {
+    kelvin: f32,
+}
+
+impl Temperature {
+    pub fn from_kelvin(k: f32) -> Temperature {
+        Temperature { kelvin: k }
+    }
+
+    pub fn from_celsius(c: f32) -> Temperature {
+        Temperature {
+            kelvin: c + ZERO_CELSIUS,
+        }
+    }
+
+    pub fn kelvin(self) -> f32 {
+        self.kelvin
+    }
+
+    pub fn celsius(self) -> f32 {
+        self.kelvin - ZERO_CELSIUS
+    }
+
+    // "60 C", "-20°C", "333.15 K", "333.15K". the unit is required in text form.
+    pub fn parse(s: &str) -> Option<Temperature> {
+        let s = s.trim();
+        let (num, unit) = match s.find(|c: char| c.is_alphabetic() || c == '°') {
+            Some(i) => (s[..i].trim(), s[i..].trim_start_matches('°').trim()),
+            None => return None,
+        };
+        let v: f32 = num.parse().ok()?;
+        match unit {
+            "K" | "k" => Some(Temperature::from_kelvin(v)),
+            "C" | "c" => Some(Temperature::from_celsius(v)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1} K ({:.1} C)", self.kelvin, self.celsius())
+    }
+}
+
+// cell files take either a bare number, which is Kelvin like every older file,
+// or a string with an explicit unit: temp_max: "60 C".
+impl<'de> Deserialize<'de> for Temperature {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Temperature, D::Error> {
+        struct TemperatureVisitor;
+
+        impl<'de> Visitor<'de> for TemperatureVisitor {
+            type Value = Temperature;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "a temperature in Kelvin, or a string like \"25 C\" or \"298.15 K\""
+                )
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Temperature, E> {
+                Ok(Temperature::from_kelvin(v as f32))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Temperature, E> {
+                Ok(Temperature::from_kelvin(v as f32))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Temperature, E> {
+                Ok(Temperature::from_kelvin(v as f32))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Temperature, E> {
+                Temperature::parse(v).ok_or_else(|| {
+                    E::custom(format!(
+                        "can't read temperature {:?}; use e.g. \"25 C\" or \"298.15 K\"",
+                        v
+                    ))
+                })
+            }
+        }
+
+        d.deserialize_any(TemperatureVisitor)
+    }
+}
+
+// written back out as bare Kelvin, which reads back to the same value
+impl Serialize for Temperature {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_f32(self.kelvin)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Charge,
+    Discharge,
+    Storage,
+}
+
+// (min, max) for each mode
+#[derive(Copy, Clone, Debug)]
+pub struct OperatingWindow {
+    pub charge: (Temperature, Temperature),
+    pub discharge: (Temperature, Temperature),
+    pub storage: (Temperature, Temperature),
+}
+
+impl OperatingWindow {
+    pub fn get_limits(&self, mode: Mode) -> (Temperature, Temperature) {
+        match mode {
+            Mode::Charge => self.charge,
+            Mode::Discharge => self.discharge,
+            Mode::Storage => self.storage,
+        }
+    }
+}
+
+// one stretch of time a module spent outside its window
+#[derive(Copy, Clone, Debug)]
+pub struct LimitViolation {
+    pub array: usize,
+    pub mode: Mode,
+    // s
+    pub start: f32,
+    pub end: f32,
+    // furthest it got past the limit, and the limit it broke
+    pub worst: Temperature,
+    pub limit: Temperature,
+}
+
+// temperatures measured or specified rather than simulated
+#[derive(Clone, Debug, Deserialize)]
+pub struct TemperatureProfile {
+    pub initial_soc: f32,
+    // (duration s, pack current A, module temperature); positive current discharges
+    pub steps: Vec<(f32, f32, Temperature)>,
+}
+
+impl Module {
+    // discharge is temp_min..temp_max. charge defaults to 0-45 C (LTO from -30 C) and
+    // storage to the discharge window, each clipped to the discharge window.
+    // inverted or NaN limits (validate reports them) clip without panicking.
+    pub fn get_operating_window(&self) -> OperatingWindow {
+        let (a, b) = (self.temp_min.kelvin(), self.temp_max.kelvin());
+        let (lo, hi) = (a.min(b), a.max(b));
+        let clip = |t: Temperature| Temperature::from_kelvin(t.kelvin().max(lo).min(hi));
+        let charge_min = self.temp_charge_min.unwrap_or(match self.chem {
+            Chem::LTO => Temperature::from_celsius(-30.0),
+            _ => Temperature::from_celsius(0.0),
+        });
+        let charge_max = self
+            .temp_charge_max
+            .unwrap_or_else(|| Temperature::from_celsius(45.0));
+        OperatingWindow {
+            charge: (clip(charge_min), clip(charge_max)),
+            discharge: (self.temp_min, self.temp_max),
+            storage: (
+                clip(self.temp_storage_min.unwrap_or(self.temp_min)),
+                clip(self.temp_storage_max.unwrap_or(self.temp_max)),
+            ),
+        }
+    }
+}
+
+impl ThermalTrace {
+    // walks the trace and groups consecutive out-of-window points into violations.
+    // the mode of each point comes from the sign of its current.
+    pub fn check_window(&self, w: &OperatingWindow) -> Vec<LimitViolation> {
+        let mut out: Vec<LimitViolation> = Vec::new();
+        let mut open: Option<LimitViolation> = None;
+        for p in &self.points {
+            let mode = if p.current > REST_CURRENT {
+                Mode::Discharge
+            } else if p.current < -REST_CURRENT {
+                Mode::Charge
+            } else {
+                Mode::Storage
+            };
+            let (lo, hi) = w.get_limits(mode);
+            let broken = if p.temp < lo.kelvin() {
+                Some(lo)
+            } else if p.temp > hi.kelvin() {
+                Some(hi)
+            } else {
+                None
+            };
+            match (broken, open.as_mut()) {
+                (Some(limit), Some(v)) if v.mode == mode && v.limit == limit => {
+                    v.end = p.time;
+                    if (p.temp - limit.kelvin()).abs() > (v.worst.kelvin() - limit.kelvin()).abs() {
+                        v.worst = Temperature::from_kelvin(p.temp);
+                    }
+                }
+                (b, _) => {
+                    if let Some(v) = open.take() {
+                        out.push(v);
+                    }
+                    open = b.map(|limit| LimitViolation {
+                        array: self.array,
+                        mode,
+                        start: p.time,
+                        end: p.time,
+                        worst: Temperature::from_kelvin(p.temp),
+                        limit,
+                    });
+                }
+            }
+        }
+        out.extend(open);
+        out
+    }
+}
+
+impl Battery {
+    // violations for every module array over simulated (Battery::simulate) or
+    // specified (TemperatureProfile) traces
+    pub fn check_operating_window(&self, traces: &[ThermalTrace]) -> Vec<LimitViolation> {
+        traces
+            .iter()
+            .flat_map(|t| t.check_window(&self.module_array[t.array].module.get_operating_window()))
+            .collect()
+    }
+
+    // a specified profile turned into the same traces the simulation makes; every module
+    // is taken to be at the profile temperature
+    pub fn trace_temperatures(&self, profile: &TemperatureProfile) -> Vec<ThermalTrace> {
+        self.module_array
+            .iter()
+            .enumerate()
+            .map(|(i, ma)| {
+                let parallel = ma.parallel.max(1) as f32;
+                let (mut t, mut soc) = (0.0_f32, profile.initial_soc);
+                let mut points: Vec<TracePoint> = Vec::new();
+                for &(duration, pack_current, temp) in &profile.steps {
+                    let current = pack_current / parallel;
+                    // a point at each end of the step, so violations span the whole step
+                    for k in 0..2 {
+                        points.push(TracePoint {
+                            time: t,
+                            current,
+                            soc,
+                            temp: temp.kelvin(),
+                            heat: 0.0,
+                        });
+                        if k == 0 {
+                            t += duration;
+                            soc -= duration * current / (ma.module.q * 3600.0);
+                        }
+                    }
+                }
+                ThermalTrace {
+                    array: i,
+                    temp_max: ma.module.temp_max.kelvin(),
+                    points,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // swapped limits are a validation error, not a panic
+    #[test]
+    fn inverted_limits_do_not_panic() {
+        let mut cell = read_cell("m50t_21700", std::path::Path::new("")).unwrap();
+        std::mem::swap(&mut cell.temp_min, &mut cell.temp_max);
+        let w = cell.get_operating_window();
+        assert!(w.charge.0.kelvin() <= w.charge.1.kelvin());
+        assert!(w.storage.1.kelvin() <= cell.temp_min.kelvin());
+    }
+}
@@ -1,5 +1,6 @@
 /*
-    placeholder for thermal battery design procedures & data structures
+    thermal battery design procedures & data structures.
+    implemented: lumped transient model of each module over a current profile.
     # things to model:
     - total thermal output of battery at peak + derate, average, minimum.
     - heat power loss through enclosure via surface area and bulk conductivity
@@ -24,31 +25,358 @@
 
 use crate::*;
 
-// coolant properties in the static sense
+// s between trace points within a profile step; steps always end on a point
+const TRACE_INTERVAL: f32 = 1.0;
+
+// one row of a coolant property table
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct FluidState {
+    // K
+    pub temp: f32,
+    // kg/m3
+    pub density: f32,
+    // J/kg-K
+    pub specific_heat: f32,
+    // dynamic viscosity, Pa-s
+    pub viscosity: f32,
+    // W/m-K
+    pub conductivity: f32,
+}
+
+// coolant properties, tabulated against temperature (see coolant.rs for the library).
 // if it's not covered here, it's irrelevant to the thermal design.
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct Coolant {
-    density: f32,
-    specific_heat: f32,
-    freezing_point: f32,
-    boiling_point: f32,
-    flammable: bool,
-    conductive: bool,
-    // fill in...
+    pub name: String,
+    // rows in increasing temperature; values are interpolated linearly between them
+    pub table: Vec<FluidState>,
+    // K
+    pub freezing_point: f32,
+    pub boiling_point: f32,
+    pub flammable: bool,
+    pub conductive: bool,
+    // kg/mol; used to get mole fractions when mixing
+    pub molar_mass: f32,
+    // J/mol; only needed for components that set a mixture's freeze point (water)
+    #[serde(default)]
+    pub heat_of_fusion: Option<f32>,
+    // J/mol; only for volatile components that set a mixture's boiling point (water)
+    #[serde(default)]
+    pub heat_of_vaporization: Option<f32>,
 }
 
 // allows for mixtures of different chemical coolants,
-// e.g. 50% ethylene glycol, 50% water
+// e.g. 50% ethylene glycol, 50% water. fractions are by mass.
+#[derive(Clone, Debug)]
 pub struct CoolantMix {
-    coolants: Vec<Coolant>,
-    fractions: Vec<f32>,
+    pub coolants: Vec<Coolant>,
+    pub fractions: Vec<f32>,
 }
 
-// general properties of a thermal management system
-#[derive(Copy, Clone, Debug)]
+// general properties of a thermal management system (see coldplate.rs)
+#[derive(Clone, Debug)]
 pub struct ThermalParams {
-    coolant: Coolant,
-    flow_rate: f32,
-    heat_k: f32,
-    // fill in...
+    pub coolant: Coolant,
+    // coolant volume flow, m3/s; zero to size it from coolant_rise
+    pub flow_rate: f32,
+    // cell-to-plate contact conductance through casing, TIM and plate, W/m2-K
+    pub heat_k: f32,
+    // coolant inlet temperature, K
+    pub inlet_temp: f32,
+    // hottest the cells may get at the design load, K
+    pub cell_temp_max: f32,
+    // allowed coolant temperature rise from inlet to outlet, K
+    pub coolant_rise: f32,
+    // pack current the cooling is sized for, A
+    pub design_current: f32,
+}
+
+// where a module's heat goes. one lumped path per module, to a sink at fixed temperature.
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub enum HeatPath {
+    // convection to still or moving air over the module's whole surface.
+    // h in W/m2-K (5-10 natural, 20-100 forced), temp in K.
+    Ambient { h: f32, temp: f32 },
+    // conduction into a cold plate or coolant loop; r_th in K/W per module, temp in K.
+    Coolant { r_th: f32, temp: f32 },
+}
+
+impl HeatPath {
+    // W/K between one module and the sink
+    pub fn get_conductance(&self, m: &Module) -> f32 {
+        match *self {
+            HeatPath::Ambient { h, .. } => h * m.get_surface_area(),
+            HeatPath::Coolant { r_th, .. } => 1.0 / r_th.max(1e-6),
+        }
+    }
+
+    pub fn get_sink_temp(&self) -> f32 {
+        match *self {
+            HeatPath::Ambient { temp, .. } | HeatPath::Coolant { temp, .. } => temp,
+        }
+    }
+}
+
+// piecewise-constant pack current: a drive cycle, a charge, or both.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CurrentProfile {
+    pub initial_soc: f32,
+    // K; every module starts here
+    pub initial_temp: f32,
+    pub heat_path: HeatPath,
+    // (duration s, pack current A); positive discharges, negative charges
+    pub steps: Vec<(f32, f32)>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TracePoint {
+    pub time: f32,
+    // current through one module, A
+    pub current: f32,
+    pub soc: f32,
+    // K
+    pub temp: f32,
+    // heat generated in one module, W
+    pub heat: f32,
+}
+
+// what one module of one ModuleArray went through during a profile
+#[derive(Clone, Debug)]
+pub struct ThermalTrace {
+    pub array: usize,
+    pub temp_max: f32,
+    pub points: Vec<TracePoint>,
+}
+
+// worst case heat for sizing cooling: charging or discharging at `current`, at
+// whatever SoC makes the most of `heat(current, soc)`, W
+fn get_worst_heat(current: f32, heat: impl Fn(f32, f32) -> f32) -> f32 {
+    let mut worst = 0.0_f32;
+    for k in 0..=10 {
+        let soc = k as f32 / 10.0;
+        for &i in [current, -current].iter() {
+            worst = worst.max(heat(i, soc));
+        }
+    }
+    worst
+}
+
+impl Module {
+    // exposed surface of the module's own shape, m2
+    pub fn get_surface_area(&self) -> f32 {
+        let d = self.dims;
+        match self.shape {
+            Shape::Cylinder => PI * d[0] * d[1] + 0.5 * PI * d[0] * d[0],
+            _ => 2.0 * (d[0] * d[1] + d[1] * d[2] + d[0] * d[2]),
+        }
+    }
+
+    // irreversible heat, W: I^2 R with the SoC-dependent resistance
+    pub fn get_heat_irreversible(&self, current: f32, soc: f32) -> f32 {
+        current * current * self.get_ir_dc(&soc.clamp(0.0, 1.0))
+    }
+
+    // reversible (entropic) heat, W: -I T dU/dT, with current positive on discharge.
+    // every series cell sees the whole string current, so it scales with series count.
+    // negative means the module is absorbing heat.
+    pub fn get_heat_reversible(&self, current: f32, soc: f32, temp: f32) -> f32 {
+        -current * temp * self.series.max(1) as f32 * entropic_coefficient(&self.chem, soc)
+    }
+
+    // total heat generated in the module at a current (A, positive discharging),
+    // SoC and temperature (K), W
+    pub fn get_heat_generation(&self, current: f32, soc: f32, temp: f32) -> f32 {
+        self.get_heat_irreversible(current, soc) + self.get_heat_reversible(current, soc, temp)
+    }
+
+    // worst case heat of one module carrying `current`, W; see get_worst_heat
+    pub fn get_design_heat(&self, current: f32, temp: f32) -> f32 {
+        get_worst_heat(current, |i, soc| self.get_heat_generation(i, soc, temp))
+    }
+
+    // J/K. specific_heat is taken as J/kg-K, which is what the cell files hold.
+    pub fn get_heat_capacity(&self) -> f32 {
+        self.mass * self.specific_heat
+    }
+
+    // lumped-capacitance model of one module carrying `current(t)` (A, per module).
+    // heat comes from get_heat_generation; the module is one isothermal node
+    // behind a single conductance to the sink. explicit Euler, with the time step
+    // kept well under the node's time constant. the trace holds a point every
+    // TRACE_INTERVAL, the end of every step, and the step that first passes temp_max.
+    pub fn simulate(&self, profile: &CurrentProfile, parallel: f32) -> Vec<TracePoint> {
+        let c = self.get_heat_capacity().max(1e-6);
+        let g = profile.heat_path.get_conductance(self);
+        let sink = profile.heat_path.get_sink_temp();
+        let dt_max = (0.1 * c / g.max(1e-9)).clamp(1e-3, 1.0);
+        let (mut t, mut soc, mut temp) = (0.0_f32, profile.initial_soc, profile.initial_temp);
+        let mut points: Vec<TracePoint> = vec![TracePoint {
+            time: 0.0,
+            current: 0.0,
+            soc,
+            temp,
+            heat: 0.0,
+        }];
+        let temp_max = self.temp_max.kelvin();
+        let mut next_point = TRACE_INTERVAL;
+        for &(duration, pack_current) in &profile.steps {
+            let i = pack_current / parallel;
+            let n = (duration / dt_max).ceil().max(1.0) as usize;
+            let dt = duration / n as f32;
+            // from the step's start, so a million tiny steps don't drift in f32
+            let start = t;
+            for k in 0..n {
+                let heat = self.get_heat_generation(i, soc, temp);
+                let before = temp;
+                temp += dt * (heat - g * (temp - sink)) / c;
+                soc -= dt * i / (self.q * 3600.0);
+                t = start + (k + 1) as f32 * dt;
+                let crossed = before <= temp_max && temp > temp_max;
+                if !(k + 1 == n || t >= next_point || crossed) {
+                    continue;
+                }
+                while next_point <= t {
+                    next_point += TRACE_INTERVAL;
+                }
+                points.push(TracePoint {
+                    time: t,
+                    current: i,
+                    soc,
+                    temp,
+                    heat,
+                });
+            }
+        }
+        points
+    }
+}
+
+impl ThermalTrace {
+    pub fn get_peak_temp(&self) -> f32 {
+        self.points.iter().map(|p| p.temp).fold(f32::MIN, f32::max)
+    }
+
+    // first time the module goes over its temp_max, if it does
+    pub fn get_violation(&self) -> Option<f32> {
+        self.points.iter().find(|p| p.temp > self.temp_max).map(|p| p.time)
+    }
+
+    // true if the profile ran the module past empty or full
+    pub fn exceeds_soc(&self) -> bool {
+        self.points.iter().any(|p| p.soc < 0.0 || p.soc > 1.0)
+    }
+
+    // time (s), current (A), soc, temperature (K), heat (W); one row per trace point
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("time,current,soc,temp,heat\n");
+        for p in &self.points {
+            out.push_str(&format!(
+                "{:.3},{:.4},{:.5},{:.3},{:.4}\n",
+                p.time, p.current, p.soc, p.temp, p.heat
+            ));
+        }
+        out
+    }
+}
+
+impl Battery {
+    // whole-pack heat generation at a pack current (A, positive discharging), W
+    pub fn get_heat_generation(&self, current: f32, soc: f32, temp: f32) -> f32 {
+        self.module_array
+            .iter()
+            .map(|ma| {
+                let i = current / ma.parallel.max(1) as f32;
+                ma.get_module_count() as f32 * ma.module.get_heat_generation(i, soc, temp)
+            })
+            .sum()
+    }
+
+    // worst case heat of the pack carrying `current`, W; see get_worst_heat
+    pub fn get_design_heat(&self, current: f32, temp: f32) -> f32 {
+        get_worst_heat(current, |i, soc| self.get_heat_generation(i, soc, temp))
+    }
+
+    // every module in an array carries pack current / parallel, so one trace per array
+    pub fn simulate(&self, profile: &CurrentProfile) -> Vec<ThermalTrace> {
+        self.module_array
+            .iter()
+            .enumerate()
+            .map(|(i, ma)| ThermalTrace {
+                array: i,
+                temp_max: ma.module.temp_max.kelvin(),
+                points: ma.module.simulate(profile, ma.parallel.max(1) as f32),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one 202 Ah LFP cell, rnom 0.5 mOhm, at 100 A and SoC 0.45:
+    //  I2R: r = rnom / (4.5 (0.1 soc + 0.2)) = 0.4535 mOhm, 4.535 W either direction
+    //  entropic: dU/dT = -0.065 mV/K (halfway between the 0.4 and 0.5 rows), so
+    //  -I T dU/dT = 1.938 W given off discharging at 298.15 K, taken in charging
+    #[test]
+    fn heat_splits_into_i2r_and_entropic() {
+        let cell = read_cell("lfp_202ah", std::path::Path::new("")).unwrap();
+        let (soc, temp) = (0.45, 298.15);
+        for &i in &[100.0, -100.0] {
+            assert!((cell.get_heat_irreversible(i, soc) - 4.535).abs() < 1e-3);
+        }
+        let discharge = cell.get_heat_reversible(100.0, soc, temp);
+        assert!((discharge - 1.938).abs() < 1e-3, "{}", discharge);
+        assert!((cell.get_heat_reversible(-100.0, soc, temp) + discharge).abs() < 1e-6);
+        // a full cycle at one SoC leaves only the I2R
+        let cycle = cell.get_heat_generation(100.0, soc, temp)
+            + cell.get_heat_generation(-100.0, soc, temp);
+        assert!((cycle - 2.0 * 4.535).abs() < 2e-3);
+        // the worst case is charging when empty: 5.556 W of I2R (r = rnom / 0.9) plus
+        // 100 A x 298.15 K x 0.15 mV/K = 4.472 W entropic
+        assert!((cell.get_design_heat(100.0, temp) - 10.028).abs() < 2e-3);
+    }
+
+    // no cooling and no entropic heat: c dT/dt = I^2 rnom / (4.5 (0.1 soc + 0.2)) with
+    // soc falling linearly, so T = T0 + 10 I rnom 3600 q / (4.5 c) ln(a0 / a1),
+    // a = 0.1 soc + 0.2
+    #[test]
+    fn adiabatic_heating_matches_closed_form() {
+        let mut cell = read_cell("lfp_202ah", std::path::Path::new("")).unwrap();
+        cell.chem = Chem::Other;
+        let (i, duration, t0) = (101.0, 1800.0, 298.15);
+        let profile = CurrentProfile {
+            initial_soc: 1.0,
+            initial_temp: t0,
+            heat_path: HeatPath::Ambient { h: 0.0, temp: t0 },
+            steps: vec![(duration, i)],
+        };
+        let points = cell.simulate(&profile, 1.0);
+        let end = points.last().unwrap();
+        let soc1 = 1.0 - i * duration / (3600.0 * cell.q);
+        let c = cell.get_heat_capacity();
+        let rise = 10.0 * i * cell.rnom * 3600.0 * cell.q / (4.5 * c)
+            * ((0.1 + 0.2) / (0.1 * soc1 + 0.2)).ln();
+        assert!((end.soc - soc1).abs() < 1e-4);
+        assert!((end.temp - t0 - rise).abs() < 0.01 * rise, "{} vs {}", end.temp - t0, rise);
+    }
+
+    // a stiff cold plate forces millisecond steps; the trace still has one point a second
+    #[test]
+    fn trace_is_thinned_to_the_interval() {
+        let cell = read_cell("tesla_21700", std::path::Path::new("")).unwrap();
+        let profile = CurrentProfile {
+            initial_soc: 1.0,
+            initial_temp: 298.15,
+            heat_path: HeatPath::Coolant {
+                r_th: 1e-4,
+                temp: 298.15,
+            },
+            steps: vec![(60.0, 10.0), (40.0, -5.0)],
+        };
+        let points = cell.simulate(&profile, 1.0);
+        assert!(points.len() <= 102, "{} points", points.len());
+        assert!(points.iter().any(|p| (p.time - 60.0).abs() < 1e-3));
+        assert!((points.last().unwrap().time - 100.0).abs() < 1e-2);
+    }
 }
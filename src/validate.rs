@@ -0,0 +1,380 @@
+/*
+    sanity checks on a loaded cell or module before anything is computed from it:
+    impossible values are errors, implausible ones are warnings. bounds are loose on
+    purpose; they're there to catch typos and unit mix-ups, not to judge a datasheet.
+
+    Copyright (C) 2020 Paul Hansel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as
+    published by the Free Software Foundation, either version 3 of the
+    License, or (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::*;
+
+// J/kg-K; real cells sit around 800-1100
+const SPECIFIC_HEAT_RANGE: (f32, f32) = (500.0, 1500.0);
+// modules carry housings, busbars and electronics, so their floor is lower
+const MODULE_DENSITY_FACTOR: f32 = 0.5;
+// anything hotter than this can't be a Kelvin cell limit written on purpose
+const TEMP_CEILING: f32 = 450.0;
+// continuous C-rates past this are almost certainly a typo
+const MAX_C_RATE: f32 = 50.0;
+// Ah; coin cells to the biggest prismatics. a module's q over its parallel count has
+// to land in here, or q isn't parallel x cell q
+const CELL_Q_RANGE: (f32, f32) = (0.02, 1500.0);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    // cell file field the finding is about
+    pub field: &'static str,
+    pub message: String,
+}
+
+// (Wh/kg, Wh/L) ranges a single cell of the chemistry plausibly falls in
+fn energy_density_range(chem: &Chem) -> Option<((f32, f32), (f32, f32))> {
+    match chem {
+        Chem::NMC => Some(((120.0, 300.0), (250.0, 800.0))),
+        Chem::NCA => Some(((180.0, 320.0), (400.0, 800.0))),
+        Chem::LFP => Some(((80.0, 200.0), (150.0, 450.0))),
+        Chem::LMO => Some(((80.0, 180.0), (150.0, 450.0))),
+        Chem::LTO => Some(((40.0, 120.0), (80.0, 250.0))),
+        Chem::NiMH => Some(((30.0, 120.0), (100.0, 400.0))),
+        Chem::Other => None,
+    }
+}
+
+// false for NaN as well as for zero and below, so a number that didn't come through
+// as one is caught rather than passed
+fn positive(x: f32) -> bool {
+    x > 0.0
+}
+
+impl Module {
+    pub fn validate(&self) -> Vec<Finding> {
+        let mut out: Vec<Finding> = Vec::new();
+        let mut add = |severity: Severity, field: &'static str, message: String| {
+            out.push(Finding {
+                severity,
+                field,
+                message,
+            })
+        };
+
+        // voltages
+        if !(self.vmin < self.vnom && self.vnom < self.vmax) {
+            add(
+                Severity::Error,
+                "vnom",
+                format!(
+                    "voltages must run vmin < vnom < vmax, got {} < {} < {}",
+                    self.vmin, self.vnom, self.vmax
+                ),
+            );
+        }
+
+        // size and mass
+        let dims_needed = match self.shape {
+            Shape::Cylinder => 2,
+            _ => 3,
+        };
+        if self.dims.iter().take(dims_needed).any(|d| !positive(*d)) {
+            add(
+                Severity::Error,
+                "dims",
+                format!(
+                    "the first {} dims must be positive, got {:?}",
+                    dims_needed, self.dims
+                ),
+            );
+        }
+        for (field, v) in [("mass", self.mass), ("q", self.q)].iter() {
+            if !positive(*v) {
+                add(
+                    Severity::Error,
+                    field,
+                    format!("must be positive, got {}", v),
+                );
+            }
+        }
+        if !positive(self.rnom) {
+            add(
+                Severity::Warning,
+                "rnom",
+                format!(
+                    "resistance should be positive, got {}; heat and losses will read zero",
+                    self.rnom
+                ),
+            );
+        }
+        if !positive(self.max_current_continuous) {
+            add(
+                Severity::Warning,
+                "max_current_continuous",
+                format!("should be positive, got {}", self.max_current_continuous),
+            );
+        } else if self.q > 0.0 && self.max_current_continuous / self.q > MAX_C_RATE {
+            add(
+                Severity::Warning,
+                "max_current_continuous",
+                format!(
+                    "{:.0} A is {:.0}C continuous; check the units",
+                    self.max_current_continuous,
+                    self.max_current_continuous / self.q
+                ),
+            );
+        }
+
+        // series/parallel have to agree with what a cell of the chemistry can be
+        let series = self.series.max(1) as f32;
+        if self.input_type == ModType::Cell && (self.series != 1 || self.parallel != 1) {
+            add(
+                Severity::Warning,
+                "series",
+                format!(
+                    "a cell should be 1S1P, got {}S{}P; is input_type meant to be module?",
+                    self.series, self.parallel
+                ),
+            );
+        }
+        if self.series < 1 || self.parallel < 1 {
+            add(
+                Severity::Error,
+                "series",
+                format!(
+                    "series and parallel must be at least 1, got {}S{}P",
+                    self.series, self.parallel
+                ),
+            );
+        }
+        let (qlo, qhi) = CELL_Q_RANGE;
+        let cell_q = self.q / self.parallel.max(1) as f32;
+        if self.input_type == ModType::Module && self.q > 0.0 && !(qlo..=qhi).contains(&cell_q) {
+            add(
+                Severity::Warning,
+                "parallel",
+                format!(
+                    "{} Ah over {} in parallel is {} Ah per cell; q is the module's capacity, \
+                     parallel x cell q",
+                    self.q, self.parallel, cell_q
+                ),
+            );
+        }
+        let (cmin, cnom, cmax, ..) = defaults_from_chem(&self.chem);
+        if cnom > 0.0 {
+            let v = self.vnom / series;
+            if v < 0.85 * cnom || v > 1.15 * cnom {
+                add(
+                    Severity::Warning,
+                    "series",
+                    format!(
+                        "{:.2} V nominal over {} in series is {:.2} V per cell; {:?} cells are about {:.2} V",
+                        self.vnom, self.series, v, self.chem, cnom
+                    ),
+                );
+            }
+            if self.vmax / series > cmax + 0.2 || self.vmin / series < cmin - 0.7 {
+                add(
+                    Severity::Warning,
+                    "vmax",
+                    format!(
+                        "{:.2}-{:.2} V per cell is outside what {:?} allows ({:.1}-{:.1} V)",
+                        self.vmin / series,
+                        self.vmax / series,
+                        self.chem,
+                        cmin,
+                        cmax
+                    ),
+                );
+            }
+        }
+
+        // energy density, which is where most capacity, mass and dims typos show up
+        let wh = self.vnom * self.q;
+        let volume = self.get_volume();
+        if let (Some((grav, vol)), true) = (
+            energy_density_range(&self.chem),
+            self.mass > 0.0 && volume > 0.0,
+        ) {
+            let floor = match self.input_type {
+                ModType::Module => MODULE_DENSITY_FACTOR,
+                ModType::Cell => 1.0,
+            };
+            let checks = [
+                ("mass", "Wh/kg", wh / self.mass, grav),
+                ("dims", "Wh/L", wh / (volume * 1000.0), vol),
+            ];
+            for (field, unit, v, (lo, hi)) in checks.iter() {
+                if *v < lo * floor || v > hi {
+                    add(
+                        Severity::Warning,
+                        field,
+                        format!(
+                            "{:.0} {} is outside the {:.0}-{:.0} {} usual for {:?}; check q, mass and dims",
+                            v, unit, lo * floor, hi, unit, self.chem
+                        ),
+                    );
+                }
+            }
+        }
+
+        // thermal
+        let (lo, hi) = SPECIFIC_HEAT_RANGE;
+        if !self.specific_heat.is_finite() {
+            add(
+                Severity::Error,
+                "specific_heat",
+                format!("must be a number, got {}", self.specific_heat),
+            );
+        } else if self.specific_heat > 0.0 && self.specific_heat < 5.0 {
+            add(
+                Severity::Error,
+                "specific_heat",
+                format!(
+                    "{} looks like kJ/kg-K; cell files hold J/kg-K (e.g. {})",
+                    self.specific_heat,
+                    self.specific_heat * 1000.0
+                ),
+            );
+        } else if self.specific_heat < lo || self.specific_heat > hi {
+            add(
+                Severity::Warning,
+                "specific_heat",
+                format!(
+                    "{} J/kg-K is outside the usual {:.0}-{:.0}",
+                    self.specific_heat, lo, hi
+                ),
+            );
+        }
+        if !positive(self.temp_max.kelvin() - self.temp_min.kelvin()) {
+            add(
+                Severity::Error,
+                "temp_min",
+                format!(
+                    "temp_min {} must be below temp_max {}",
+                    self.temp_min, self.temp_max
+                ),
+            );
+        }
+        for (field, t) in [("temp_max", self.temp_max), ("temp_min", self.temp_min)].iter() {
+            if !t.kelvin().is_finite() {
+                add(
+                    Severity::Error,
+                    field,
+                    format!("must be a temperature, got {}", t.kelvin()),
+                );
+            } else if t.kelvin() < 150.0 {
+                add(
+                    Severity::Error,
+                    field,
+                    format!(
+                        "{:.2} K is colder than any cell works; was it meant as \"{} C\"?",
+                        t.kelvin(),
+                        t.kelvin()
+                    ),
+                );
+            } else if t.kelvin() > TEMP_CEILING {
+                add(
+                    Severity::Warning,
+                    field,
+                    format!("{} is hotter than any cell is rated for", t),
+                );
+            }
+        }
+        if !positive(self.cycle_life) {
+            add(
+                Severity::Warning,
+                "cycle_life",
+                format!("should be positive, got {}", self.cycle_life),
+            );
+        }
+        out
+    }
+}
+
+impl Battery {
+    // warnings for every module array, with its index, if there's nothing worse;
+    // otherwise every finding comes back inside Error::Validation
+    pub fn validate(&self) -> Result<Vec<(usize, Finding)>, Error> {
+        let findings: Vec<(usize, Finding)> = self
+            .module_array
+            .iter()
+            .enumerate()
+            .flat_map(|(i, ma)| ma.module.validate().into_iter().map(move |f| (i, f)))
+            .collect();
+        if findings.iter().any(|(_, f)| f.severity == Severity::Error) {
+            Err(Error::Validation(findings))
+        } else {
+            Ok(findings)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(m: &Module) -> Vec<&'static str> {
+        m.validate()
+            .into_iter()
+            .filter(|f| f.severity == Severity::Error)
+            .map(|f| f.field)
+            .collect()
+    }
+
+    #[test]
+    fn shipped_cells_have_no_errors() {
+        for id in &["lfp_202ah", "tesla_21700", "leaf_2012"] {
+            let cell = read_cell(id, std::path::Path::new("")).unwrap();
+            assert!(errors(&cell).is_empty(), "{}: {:?}", id, cell.validate());
+        }
+    }
+
+    #[test]
+    fn nan_and_zero_are_errors() {
+        let cell = read_cell("lfp_202ah", std::path::Path::new("")).unwrap();
+        let mut m = cell;
+        m.dims[1] = f32::NAN;
+        m.mass = 0.0;
+        m.q = f32::NAN;
+        m.temp_max = Temperature::from_kelvin(f32::NAN);
+        m.specific_heat = f32::NAN;
+        let e = errors(&m);
+        for field in &["dims", "mass", "q", "temp_min", "temp_max", "specific_heat"] {
+            assert!(e.contains(field), "{} not flagged: {:?}", field, e);
+        }
+    }
+
+    // the leaf module's 66 Ah over 2P is 33 Ah cells; 0.01 Ah over 2P can't be a module,
+    // though it's a fine q for a lone cell
+    #[test]
+    fn module_capacity_per_cell() {
+        let leaf = read_cell("leaf_2012", std::path::Path::new("")).unwrap();
+        let flagged = |m: &Module| m.validate().iter().any(|f| f.field == "parallel");
+        assert!(!flagged(&leaf));
+        let mut tiny = leaf;
+        tiny.q = 0.01;
+        assert!(flagged(&tiny));
+        let mut cell = tiny;
+        cell.input_type = ModType::Cell;
+        cell.series = 1;
+        cell.parallel = 1;
+        assert!(!flagged(&cell));
+    }
+}